@@ -27,12 +27,9 @@ where
 {
     pub fn new(iter: I, column: ColSpec) -> Result<Add<I>, BuildError> {
         let mut headers = iter.headers().clone();
-        let colname = match &column {
-            ColSpec::Regex { colname, .. } => colname,
-            ColSpec::Mix { colname, .. } => colname,
-        };
+        let colname = column.colname();
 
-        if let Err(_) = headers.add(&colname) {
+        if let Err(_) = headers.add(colname) {
             return Err(BuildError::DuplicatedHeader(colname.to_string()));
         }
 
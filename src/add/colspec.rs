@@ -39,6 +39,14 @@ fn interpolate(template: &str, captures: &Captures) -> String {
 }
 
 impl ColSpec {
+    /// Nombre de la columna que esta especificación construye
+    pub fn colname(&self) -> &str {
+        match self {
+            ColSpec::Regex { colname, .. } => colname,
+            ColSpec::Mix { colname, .. } => colname,
+        }
+    }
+
     pub fn compute(&self, data: &Row, headers: &Headers) -> Result<String, col::BuildError> {
         match *self {
             ColSpec::Mix { ref coldef, .. } => match strfmt_map(&coldef, &|mut fmt: Formatter| {
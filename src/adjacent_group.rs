@@ -4,6 +4,7 @@ use crate::{
     RowStream, Headers, RowResult, GroupBuildError,
     mock::MockStream,
     error::Error,
+    group::{GroupHandler, GroupKey, WithKey},
 };
 
 /// Groups data by a set of columns.
@@ -32,6 +33,45 @@ where
     R: RowStream,
 {
     pub fn new<H>(
+        iter: I,
+        header_map: H,
+        f: F,
+        grouping: &[&str],
+    ) -> Result<AdjacentGroup<I, F>, GroupBuildError>
+    where
+        H: FnMut(Headers) -> Headers,
+    {
+        AdjacentGroup::build(iter, header_map, f, grouping)
+    }
+}
+
+impl<I, F, R> AdjacentGroup<I, WithKey<F>>
+where
+    I: RowStream,
+    F: FnMut(&GroupKey, MockStream<vec::IntoIter<RowResult>>) -> R,
+    R: RowStream,
+{
+    /// Like [new](#method.new) but the closure also receives the key of each
+    /// group. Errors are not grouped so the key is always
+    /// `GroupKey::Rows`.
+    pub fn new_with_key<H>(
+        iter: I,
+        header_map: H,
+        f: F,
+        grouping: &[&str],
+    ) -> Result<AdjacentGroup<I, WithKey<F>>, GroupBuildError>
+    where
+        H: FnMut(Headers) -> Headers,
+    {
+        AdjacentGroup::build(iter, header_map, WithKey(f), grouping)
+    }
+}
+
+impl<I, F> AdjacentGroup<I, F>
+where
+    I: RowStream,
+{
+    fn build<H>(
         iter: I,
         mut header_map: H,
         f: F,
@@ -64,25 +104,23 @@ where
     }
 }
 
-pub struct IntoIter<I, F, R>
+pub struct IntoIter<I, F>
 where
     I: Iterator<Item=RowResult>,
-    F: FnMut(MockStream<vec::IntoIter<RowResult>>) -> R,
-    R: RowStream,
+    F: GroupHandler,
 {
     iter: Peekable<I>,
     f: F,
     headers: Headers,
     old_headers: Headers,
-    current_group: Option<R::IntoIter>,
+    current_group: Option<<F::Output as IntoIterator>::IntoIter>,
     group_by: Vec<String>,
 }
 
-impl<I, F, R> Iterator for IntoIter<I, F, R>
+impl<I, F> Iterator for IntoIter<I, F>
 where
     I: Iterator<Item = RowResult>,
-    F: FnMut(MockStream<vec::IntoIter<RowResult>>) -> R,
-    R: RowStream,
+    F: GroupHandler,
 {
     type Item = RowResult;
 
@@ -117,14 +155,17 @@ where
                 None => None,
                 Some(Ok(_)) => {
                     let first_row = self.iter.next().unwrap().unwrap();
-                    let current_hash = self.headers.hash(&first_row, &self.group_by).unwrap();
+                    let current_key = match self.old_headers.key(&first_row, &self.group_by) {
+                        Ok(key) => key,
+                        Err(col) => return Some(Err(Error::ColumnNotFound(col))),
+                    };
                     let mut current_group = vec![Ok(first_row)];
 
                     loop {
                         if let Some(Ok(next_row)) = self.iter.peek() {
-                            let next_hash = self.headers.hash(next_row, &self.group_by).unwrap();
+                            let next_key = self.old_headers.key(next_row, &self.group_by);
 
-                            if next_hash == current_hash {
+                            if next_key.as_ref() == Ok(&current_key) {
                                 current_group.push(self.iter.next().unwrap());
                             } else {
                                 break;
@@ -134,8 +175,9 @@ where
                         }
                     }
 
-                    let output_stream = (self.f)(
-                        MockStream::new(current_group.into_iter(), self.old_headers.clone())
+                    let output_stream = self.f.handle(
+                        &GroupKey::Rows(current_key),
+                        MockStream::new(current_group.into_iter(), self.old_headers.clone()),
                     );
 
                     if *output_stream.headers() != self.headers {
@@ -152,15 +194,14 @@ where
     }
 }
 
-impl<I, F, R> IntoIterator for AdjacentGroup<I, F>
+impl<I, F> IntoIterator for AdjacentGroup<I, F>
 where
     I: RowStream,
-    F: FnMut(MockStream<vec::IntoIter<RowResult>>) -> R,
-    R: RowStream,
+    F: GroupHandler,
 {
     type Item = RowResult;
 
-    type IntoIter = IntoIter<I::IntoIter, F, R>;
+    type IntoIter = IntoIter<I::IntoIter, F>;
 
     fn into_iter(self) -> Self::IntoIter {
         Self::IntoIter {
@@ -174,11 +215,10 @@ where
     }
 }
 
-impl<I, F, R> RowStream for AdjacentGroup<I, F>
+impl<I, F> RowStream for AdjacentGroup<I, F>
 where
     I: RowStream,
-    F: FnMut(MockStream<vec::IntoIter<RowResult>>) -> R,
-    R: RowStream,
+    F: GroupHandler,
 {
     fn headers(&self) -> &Headers {
         &self.headers
@@ -193,7 +233,7 @@ mod tests {
         mock::MockStream,
         error::Error,
     };
-    use super::AdjacentGroup;
+    use super::{AdjacentGroup, GroupKey};
 
     #[test]
     fn test_adjacent_group() {
@@ -259,6 +299,39 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_adjacent_group_with_key() {
+        let iter = MockStream::from_rows(
+            vec![
+                Ok(Row::from(vec!["name", "value"])),
+                Ok(Row::from(vec!["a", "1"])),
+                Ok(Row::from(vec!["a", "2"])),
+                Ok(Row::from(vec!["b", "3"])),
+                Ok(Row::from(vec!["a", "4"])),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+
+        let mut keys = vec![];
+
+        let re = AdjacentGroup::new_with_key(iter, |headers| headers, |key, row_stream| {
+            keys.push(key.clone());
+
+            row_stream
+        }, &["name"]).unwrap();
+
+        assert_eq!(re.into_iter().count(), 4);
+        assert_eq!(
+            keys,
+            vec![
+                GroupKey::Rows(vec!["a".to_string()]),
+                GroupKey::Rows(vec!["b".to_string()]),
+                GroupKey::Rows(vec!["a".to_string()]),
+            ]
+        );
+    }
+
     #[test]
     fn test_nonmatching_headers() {
         let iter = MockStream::from_rows(
@@ -1,3 +1,5 @@
+use csv::Position;
+use std::io;
 use std::path::PathBuf;
 use std::result;
 
@@ -10,10 +12,34 @@ pub enum Error {
     // allow for errors to be grouped and streamed in groups
     Csv(csv::Error),
     InconsistentHeaders,
-    InconsistentSizeOfRows(PathBuf),
+    /// A source has different headers than the stream under a strict
+    /// [HeaderAlignment](enum.HeaderAlignment.html). Contains the label of
+    /// the source and the position of its headers.
+    InconsistentSourceHeaders(String, Position),
+    /// A source could not be read. Contains the label of the source, the
+    /// position where reading failed and the error of the CSV reader.
+    Read(String, Position, csv::Error),
+    /// A row has a different number of fields than the headers. Contains the
+    /// label of the source, see [ReaderSource](struct.ReaderSource.html), and
+    /// the position of the row in it.
+    InconsistentSizeOfRows(String, Position),
     ColBuildError(col::BuildError),
     ColumnNotFound(String),
     AggregateError(AggregateError),
+    /// An I/O operation on the given path failed, like creating a directory
+    /// or a file to flush to.
+    Io(PathBuf, io::Error),
+    /// Writing a row to the given path failed.
+    Write(PathBuf, csv::Error),
+    /// Rows were to be appended to a file with different headers.
+    HeaderMismatch(PathBuf),
+    /// A line of JSON Lines input is not a JSON object. Contains the label of
+    /// the source, the position of the line and the reason.
+    InvalidJson(String, Position, String),
+    /// A field could not be decoded with the encoding of its source under a
+    /// strict [DecodingPolicy](enum.DecodingPolicy.html). Contains the label
+    /// of the source, the position of the row and the index of the field.
+    InvalidEncoding(String, Position, usize),
 }
 
 pub type Result<T> = result::Result<T, Error>;
@@ -32,11 +58,18 @@ impl std::error::Error for Error {
     fn description(&self) -> &str {
         match *self {
             Error::Csv(_) => "CSV error",
-            Error::InconsistentSizeOfRows(_) => "inconsistent size of rows",
+            Error::InconsistentSizeOfRows(..) => "inconsistent size of rows",
             Error::InconsistentHeaders => "inconsistent headers among files",
+            Error::InconsistentSourceHeaders(..) => "inconsistent headers among files",
+            Error::Read(..) => "Could not read source",
             Error::ColBuildError(_) => "Error building a column",
             Error::ColumnNotFound(_) => "Requested unexisten column",
             Error::AggregateError(_) => "Aggregation error during process",
+            Error::Io(..) => "I/O error",
+            Error::Write(..) => "Could not write to target",
+            Error::HeaderMismatch(_) => "Target file has different headers",
+            Error::InvalidJson(..) => "Invalid JSON input",
+            Error::InvalidEncoding(..) => "Field could not be decoded",
         }
     }
 }
@@ -45,15 +78,30 @@ impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match *self {
             Error::Csv(ref e) => write!(f, "CSV error: {}", e),
-            Error::InconsistentSizeOfRows(ref p) => {
-                write!(f, "inconsistent size of rows in {:?}", p)
+            Error::InconsistentSizeOfRows(ref p, ref pos) => {
+                write!(f, "inconsistent size of rows in {} line {}", p, pos.line())
             }
             Error::InconsistentHeaders => {
                 write!(f, "inconsistent headers")
             }
+            Error::InconsistentSourceHeaders(ref s, ref pos) => {
+                write!(f, "inconsistent headers in {} line {}", s, pos.line())
+            }
+            Error::Read(ref s, ref pos, ref e) => {
+                write!(f, "Could not read {} line {}: {}", s, pos.line(), e)
+            }
             Error::ColBuildError(ref c) => write!(f, "Could not build column with reason: {:?}", c),
             Error::ColumnNotFound(ref c) => write!(f, "Requested column that was not found: {}", c),
             Error::AggregateError(ref c) => write!(f, "Column aggregation failed: {:?}", c),
+            Error::Io(ref p, ref e) => write!(f, "I/O error on {:?}: {}", p, e),
+            Error::Write(ref p, ref e) => write!(f, "Could not write to {:?}: {}", p, e),
+            Error::HeaderMismatch(ref p) => write!(f, "Headers of {:?} don't match the rows to append", p),
+            Error::InvalidJson(ref s, ref pos, ref e) => {
+                write!(f, "Invalid JSON in {} line {}: {}", s, pos.line(), e)
+            }
+            Error::InvalidEncoding(ref s, ref pos, field) => {
+                write!(f, "Could not decode field {} of {} line {}", field, s, pos.line())
+            }
         }
     }
 }
@@ -0,0 +1,247 @@
+//! Utilities for dropping rows
+use crate::{
+    Headers, Row, RowStream,
+    error::RowResult,
+};
+
+mod filterspec;
+
+pub use filterspec::{FilterSpec, NumOp};
+
+#[derive(Debug)]
+pub enum BuildError {
+    ColumnNotFound(String),
+}
+
+/// Decides which rows are kept by a [Filter](struct.Filter.html).
+///
+/// It is implemented for closures taking the headers and the row and for
+/// [FilterSpec](enum.FilterSpec.html).
+pub trait RowPredicate {
+    fn test(&mut self, headers: &Headers, row: &Row) -> bool;
+}
+
+impl<F> RowPredicate for F
+where
+    F: FnMut(&Headers, &Row) -> bool,
+{
+    fn test(&mut self, headers: &Headers, row: &Row) -> bool {
+        (self)(headers, row)
+    }
+}
+
+impl RowPredicate for FilterSpec {
+    fn test(&mut self, headers: &Headers, row: &Row) -> bool {
+        FilterSpec::test(self, headers, row)
+    }
+}
+
+/// Keeps only the rows that satisfy a predicate. Errors are passed through
+/// untouched.
+pub struct Filter<I, F> {
+    iter: I,
+    f: F,
+    headers: Headers,
+}
+
+impl<I, F> Filter<I, F>
+where
+    I: RowStream,
+    F: FnMut(&Headers, &Row) -> bool,
+{
+    pub fn new(iter: I, f: F) -> Filter<I, F> {
+        let headers = iter.headers().clone();
+
+        Filter { iter, f, headers }
+    }
+}
+
+impl<I> Filter<I, FilterSpec>
+where
+    I: RowStream,
+{
+    /// Builds a filter from a declarative spec. Fails if the column it refers
+    /// to does not exist.
+    pub fn from_spec(iter: I, spec: FilterSpec) -> Result<Filter<I, FilterSpec>, BuildError> {
+        let headers = iter.headers().clone();
+
+        if !headers.contains_key(spec.colname()) {
+            return Err(BuildError::ColumnNotFound(spec.colname().to_string()));
+        }
+
+        Ok(Filter {
+            iter,
+            f: spec,
+            headers,
+        })
+    }
+}
+
+pub struct IntoIter<I, F> {
+    iter: I,
+    f: F,
+    headers: Headers,
+}
+
+impl<I, F> Iterator for IntoIter<I, F>
+where
+    I: Iterator<Item = RowResult>,
+    F: RowPredicate,
+{
+    type Item = RowResult;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let f = &mut self.f;
+        let headers = &self.headers;
+
+        self.iter.find(|result| match result {
+            Ok(row) => f.test(headers, row),
+            Err(_) => true,
+        })
+    }
+}
+
+impl<I, F> IntoIterator for Filter<I, F>
+where
+    I: RowStream,
+    F: RowPredicate,
+{
+    type Item = RowResult;
+
+    type IntoIter = IntoIter<I::IntoIter, F>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Self::IntoIter {
+            iter: self.iter.into_iter(),
+            f: self.f,
+            headers: self.headers,
+        }
+    }
+}
+
+impl<I, F> RowStream for Filter<I, F>
+where
+    I: RowStream,
+    F: RowPredicate,
+{
+    fn headers(&self) -> &Headers {
+        &self.headers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BuildError, Filter, FilterSpec, Headers, Row, RowStream};
+    use crate::{mock::MockStream, error::Error, InputStream, ReaderSource};
+    use encoding::all::UTF_8;
+
+    #[test]
+    fn test_filter() {
+        let iter = MockStream::from_rows(
+            vec![
+                Ok(Row::from(vec!["id", "val"])),
+                Ok(Row::from(vec!["1", "40"])),
+                Ok(Row::from(vec!["2", "39"])),
+                Err(Error::InconsistentHeaders),
+                Ok(Row::from(vec!["3", "38"])),
+                Ok(Row::from(vec!["4", "37"])),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+
+        let filter = Filter::new(iter, |headers, row| {
+            let v: i32 = headers.get_field(row, "id").unwrap().parse().unwrap();
+
+            v % 2 == 0
+        });
+
+        assert_eq!(
+            *filter.headers(),
+            Headers::from_row(Row::from(vec!["id", "val"])),
+        );
+
+        let mut filter = filter.into_iter();
+
+        assert_eq!(
+            filter.next().unwrap().unwrap(),
+            Row::from(vec!["2", "39"])
+        );
+
+        match filter.next() {
+            Some(Err(Error::InconsistentHeaders)) => {},
+            _ => unreachable!(),
+        }
+
+        assert_eq!(
+            filter.next().unwrap().unwrap(),
+            Row::from(vec!["4", "37"])
+        );
+        assert!(filter.next().is_none());
+    }
+
+    #[test]
+    fn test_filter_spec() {
+        let iter = MockStream::from_rows(
+            vec![
+                Ok(Row::from(vec!["id", "val"])),
+                Ok(Row::from(vec!["1", "a"])),
+                Ok(Row::from(vec!["2", "b"])),
+                Ok(Row::from(vec!["3", "a"])),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+
+        let filter = Filter::from_spec(iter, FilterSpec::Eq {
+            colname: "val".to_string(),
+            value: "a".to_string(),
+        }).unwrap();
+
+        let results: Vec<Row> = filter.into_iter().map(|r| r.unwrap()).collect();
+
+        assert_eq!(
+            results,
+            vec![
+                Row::from(vec!["1", "a"]),
+                Row::from(vec!["3", "a"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_filter_spec_unexistent_column() {
+        let iter = MockStream::from_rows(
+            vec![
+                Ok(Row::from(vec!["id", "val"])),
+                Ok(Row::from(vec!["1", "a"])),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+
+        match Filter::from_spec(iter, FilterSpec::Eq {
+            colname: "foo".to_string(),
+            value: "a".to_string(),
+        }) {
+            Err(BuildError::ColumnNotFound(col)) => assert_eq!(col, "foo"),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_filter_rows_on_input_stream() {
+        let input = InputStream::from_readers(
+            vec![ReaderSource::from_path("test/assets/1.csv").unwrap()],
+            UTF_8,
+        );
+
+        let results: Vec<Row> = input
+            .filter_rows(|headers, row| headers.get_field(row, "a") == Some("5"))
+            .into_iter()
+            .map(|r| r.unwrap())
+            .collect();
+
+        assert_eq!(results, vec![Row::from(vec!["5", "2", "test/assets/1.csv"])]);
+    }
+}
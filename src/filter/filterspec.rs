@@ -0,0 +1,109 @@
+use regex::Regex;
+
+use crate::{Row, Headers};
+
+/// Numeric comparisons available to `FilterSpec::Num`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumOp {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+/// Declarative ways of deciding which rows are kept by a filter.
+#[derive(Debug)]
+pub enum FilterSpec {
+    /// Keeps the rows whose column is exactly the given value.
+    Eq { colname: String, value: String },
+
+    /// Keeps the rows whose column matches the regular expression.
+    Regex { colname: String, regex: Regex },
+
+    /// Keeps the rows whose column, read as a number, compares with `value`
+    /// as `op` says. Rows whose column is not a number are dropped.
+    ///
+    /// E.g. keeping the rows with a temperature above 30:
+    ///
+    /// ```rust
+    /// use csvsc::{FilterSpec, NumOp};
+    ///
+    /// let spec = FilterSpec::Num {
+    ///     colname: "temp".to_string(),
+    ///     op: NumOp::Gt,
+    ///     value: 30.0,
+    /// };
+    /// ```
+    Num { colname: String, op: NumOp, value: f64 },
+}
+
+impl FilterSpec {
+    /// The column this spec looks at
+    pub fn colname(&self) -> &str {
+        match self {
+            FilterSpec::Eq { colname, .. } => colname,
+            FilterSpec::Regex { colname, .. } => colname,
+            FilterSpec::Num { colname, .. } => colname,
+        }
+    }
+
+    /// Tells if the given row must be kept
+    pub fn test(&self, headers: &Headers, row: &Row) -> bool {
+        let field = match headers.get_field(row, self.colname()) {
+            Some(field) => field,
+            None => return false,
+        };
+
+        match *self {
+            FilterSpec::Eq { ref value, .. } => field == value,
+            FilterSpec::Regex { ref regex, .. } => regex.is_match(field),
+            FilterSpec::Num { op, value, .. } => match field.parse::<f64>() {
+                Ok(num) => match op {
+                    NumOp::Lt => num < value,
+                    NumOp::Le => num <= value,
+                    NumOp::Gt => num > value,
+                    NumOp::Ge => num >= value,
+                    NumOp::Eq => num == value,
+                    NumOp::Ne => num != value,
+                },
+                Err(_) => false,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+    use crate::{Row, Headers};
+    use super::{FilterSpec, NumOp};
+
+    #[test]
+    fn test_filterspec_num() {
+        let headers = Headers::from_row(Row::from(vec!["a"]));
+        let spec = FilterSpec::Num {
+            colname: "a".to_string(),
+            op: NumOp::Ge,
+            value: 2.0,
+        };
+
+        assert!(spec.test(&headers, &Row::from(vec!["2"])));
+        assert!(spec.test(&headers, &Row::from(vec!["3.5"])));
+        assert!(!spec.test(&headers, &Row::from(vec!["1.9"])));
+        assert!(!spec.test(&headers, &Row::from(vec!["chicken"])));
+    }
+
+    #[test]
+    fn test_filterspec_regex() {
+        let headers = Headers::from_row(Row::from(vec!["a"]));
+        let spec = FilterSpec::Regex {
+            colname: "a".to_string(),
+            regex: Regex::new("^[0-9]{4}-").unwrap(),
+        };
+
+        assert!(spec.test(&headers, &Row::from(vec!["2019-02-09"])));
+        assert!(!spec.test(&headers, &Row::from(vec!["09/02/2019"])));
+    }
+}
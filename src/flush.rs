@@ -1,6 +1,6 @@
-use csv::Writer;
-use std::collections::HashMap;
-use std::fs::{self, File};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 use std::result;
 use crate::{
@@ -8,6 +8,14 @@ use crate::{
     error::Result,
 };
 
+mod format;
+mod output;
+
+pub use format::OutputFormat;
+
+use format::RowWriter;
+use output::{Destination, Stream, read_headers};
+
 fn trim_underscores(headers: &Headers, row: &Row) -> Row {
     // FIXME bad estimate here of row size
     let mut new_row = Row::with_capacity(row.as_slice().len(), row.len());
@@ -34,17 +42,65 @@ fn trim_header_underscores(headers: &Row) -> Row {
     new_row
 }
 
+/// What to do when a target file already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WritePolicy {
+    /// Replace its contents.
+    #[default]
+    Truncate,
+
+    /// Add the rows at the end. The headers of the file must be the ones that
+    /// would be written, and are not written again. A missing line
+    /// terminator at the end of the file is added first.
+    ///
+    /// With atomic writes the whole existing file is copied to the temporary
+    /// file before appending, so it costs as much as rewriting it.
+    Append,
+
+    /// Fail with an error for the rows that would go to it. With atomic
+    /// writes the file is checked again when the temporary file is moved to
+    /// its place, and an error is emitted at the end of the stream if it was
+    /// created in the meantime.
+    FailIfExists,
+}
+
+/// Label used in errors for rows flushed to a `FlushTarget::Writer`
+pub const WRITER_LABEL: &str = "<writer>";
+
 pub enum FlushTarget {
     Column(String),
     Path(PathBuf),
+    /// Every row is written to the given writer. Atomic writes and write
+    /// policies don't apply to it.
+    Writer(Box<dyn Write + Send>),
+}
+
+impl FlushTarget {
+    /// Writes every row to the standard output
+    pub fn stdout() -> FlushTarget {
+        FlushTarget::Writer(Box::new(io::stdout()))
+    }
 }
 
 /// Flushes the rows to the destination specified by a column.
 ///
 /// Fields starting with underscore are not written.
+///
+/// Rows are written as CSV or as JSON Lines, see
+/// [with_format](#method.with_format).
+///
+/// Targets whose path ends in `.gz` or `.zst` are compressed if the `gzip` or
+/// `zstd` cargo feature is enabled. With
+/// [with_atomic_writes](#method.with_atomic_writes) each target is written to
+/// a temporary file that is renamed once the stream is consumed, so readers
+/// never see a half written file.
 pub struct Flush<I> {
     iter: I,
     target: FlushTarget,
+    atomic: bool,
+    max_open_files: Option<usize>,
+    policy: WritePolicy,
+    format: OutputFormat,
 }
 
 impl<I> Flush<I>
@@ -57,66 +113,297 @@ where
                 return Err(Error::ColumnNotFound(s));
             }
 
-            Ok(Flush { iter, target: FlushTarget::Column(s) })
+            Ok(Flush { iter, target: FlushTarget::Column(s), atomic: false, max_open_files: None, policy: WritePolicy::Truncate, format: OutputFormat::Csv })
         } else {
-            Ok(Flush { iter, target })
+            Ok(Flush { iter, target, atomic: false, max_open_files: None, policy: WritePolicy::Truncate, format: OutputFormat::Csv })
+        }
+    }
+
+    /// Writes every target to a temporary file in the same directory and
+    /// renames it to its final path when the stream ends. If the stream is
+    /// not consumed completely the temporary files are removed.
+    pub fn with_atomic_writes(self) -> Flush<I> {
+        Flush {
+            atomic: true,
+            ..self
+        }
+    }
+
+    /// Sets the format of the targets whose extension doesn't tell, CSV by
+    /// default. Paths ending in `.csv` are always written as CSV and those
+    /// ending in `.jsonl` or `.ndjson`, optionally followed by a compression
+    /// extension, as JSON Lines.
+    pub fn with_format(self, format: OutputFormat) -> Flush<I> {
+        Flush {
+            format,
+            ..self
+        }
+    }
+
+    /// Sets what to do with target files that already exist, by default they
+    /// are truncated.
+    pub fn with_policy(self, policy: WritePolicy) -> Flush<I> {
+        Flush {
+            policy,
+            ..self
+        }
+    }
+
+    /// Limits the number of files kept open at the same time. When a new
+    /// target needs to be opened the least recently used one is closed, and
+    /// reopened in append mode if more rows go to it.
+    pub fn with_max_open_files(self, max: usize) -> Flush<I> {
+        Flush {
+            max_open_files: Some(max),
+            ..self
+        }
+    }
+}
+
+/// A target with an open writer
+struct Target {
+    writer: RowWriter,
+    destination: Destination,
+    /// Used to find the least recently used target when too many are open
+    last_used: u64,
+}
+
+impl Target {
+    fn create(path: &Path, atomic: bool, policy: WritePolicy, append: bool, format: OutputFormat, headers: &Row) -> result::Result<Target, Error> {
+        let (destination, output) = Destination::create(path, atomic, policy, append)
+            .map_err(|e| Error::Io(path.to_path_buf(), e))?;
+
+        Ok(Target {
+            writer: RowWriter::new(format, output, headers),
+            destination,
+            last_used: 0,
+        })
+    }
+
+    /// Opens again a target that was closed, appending to what was already
+    /// written
+    fn reopen(destination: Destination, format: OutputFormat, headers: &Row) -> result::Result<Target, (Destination, Error)> {
+        match destination.reopen() {
+            Ok(output) => Ok(Target {
+                writer: RowWriter::new(format, output, headers),
+                destination,
+                last_used: 0,
+            }),
+            Err(e) => {
+                let path = destination.path().to_path_buf();
+
+                Err((destination, Error::Io(path, e)))
+            },
+        }
+    }
+
+    /// Writes whatever is left and closes the file, keeping it where it is
+    fn close(self) -> result::Result<Destination, Error> {
+        let path = self.destination.path().to_path_buf();
+        let output = match self.writer.into_inner() {
+            Ok(output) => output,
+            Err(e) => {
+                self.destination.discard();
+
+                return Err(Error::Io(path, e));
+            },
+        };
+
+        if let Err(e) = output.finish() {
+            self.destination.discard();
+
+            return Err(Error::Io(path, e));
+        }
+
+        Ok(self.destination)
+    }
+
+    /// Writes whatever is left and moves the file to its final location
+    fn finish(self) -> result::Result<(), Error> {
+        let destination = self.close()?;
+        let path = destination.path().to_path_buf();
+
+        destination.commit().map_err(|e| Error::Io(path, e))
+    }
+}
+
+/// Where the rows of a running flush go
+enum Route {
+    Column(String),
+    Path(PathBuf),
+    /// Taken once its target is created. It is not put back if writing the
+    /// headers fails, so every later row fails too.
+    Writer(Option<Box<dyn Write + Send>>),
+}
+
+impl From<FlushTarget> for Route {
+    fn from(target: FlushTarget) -> Route {
+        match target {
+            FlushTarget::Column(colname) => Route::Column(colname),
+            FlushTarget::Path(path) => Route::Path(path),
+            FlushTarget::Writer(writer) => Route::Writer(Some(writer)),
         }
     }
 }
 
 pub struct IntoIter<I> {
-    targets: HashMap<PathBuf, Writer<File>>,
+    targets: HashMap<PathBuf, Target>,
+    /// Targets closed to keep the number of open files under the limit
+    closed: HashMap<PathBuf, Destination>,
     headers: Headers,
-    target: FlushTarget,
+    /// Headers as they are written, without the columns starting with
+    /// underscore
+    header_row: Row,
+    target: Route,
+    atomic: bool,
+    max_open_files: Option<usize>,
+    policy: WritePolicy,
+    format: OutputFormat,
+    uses: u64,
     iter: I,
+    /// Errors found while closing targets, emitted before the next row
+    errors: VecDeque<Error>,
+    finished: bool,
 }
 
 impl<I> IntoIter<I> {
-    fn get_target(&mut self, row: &Row) -> result::Result<&mut Writer<File>, Error> {
-        let header_row = trim_header_underscores(self.headers.as_row());
+    fn get_target(&mut self, row: &Row) -> result::Result<&mut Target, Error> {
+        let path = match self.target {
+            // can unwrap because we checked the existence of the field
+            // while building the Flush
+            Route::Column(ref colname) => PathBuf::from(self.headers.get_field(row, colname).unwrap()),
+            Route::Path(ref path) => path.clone(),
+            Route::Writer(_) => PathBuf::from(WRITER_LABEL),
+        };
 
-        // TODO think about returning an error instead of unwrapping maybe
-        match self.target {
-            FlushTarget::Column(ref colname) => {
-                // can unwrap because we checked the existence of the field
-                // while building the Flush
-                let target = PathBuf::from(self.headers.get_field(&row, &colname).unwrap());
+        if !self.targets.contains_key(&path) {
+            // make room before opening so the limit is never exceeded
+            self.close_least_recently_used();
 
-                if self.targets.contains_key(&target) {
-                    Ok(self.targets.get_mut(&target).unwrap())
-                } else {
-                    let dirname = Path::new(&target).parent().expect("Does not have a parent");
-                    fs::create_dir_all(dirname).expect("Could not create directory");
+            let target = match self.closed.remove(&path) {
+                Some(destination) => match Target::reopen(destination, self.format_for(&path), &self.header_row) {
+                    Ok(target) => target,
+                    Err((destination, e)) => {
+                        self.closed.insert(path, destination);
 
-                    let mut writer = Writer::from_path(&target)
-                        .expect(&format!("Cannot write to target {:?}", &target));
+                        return Err(e);
+                    },
+                },
+                None => self.create_target(&path)?,
+            };
 
-                    writer
-                        .write_record(&header_row)
-                        .expect("Could not write headers");
+            self.targets.insert(path.clone(), target);
+        }
 
-                    self.targets.insert(target.to_path_buf(), writer);
+        self.uses += 1;
 
-                    Ok(self.targets.get_mut(&target).unwrap())
-                }
+        let target = self.targets.get_mut(&path).unwrap();
+
+        target.last_used = self.uses;
+
+        Ok(target)
+    }
+
+    /// The format of the given target
+    fn format_for(&self, path: &Path) -> OutputFormat {
+        match self.target {
+            Route::Writer(_) => self.format,
+            _ => OutputFormat::from_path(path).unwrap_or(self.format),
+        }
+    }
+
+    fn create_target(&mut self, path: &Path) -> result::Result<Target, Error> {
+        let header_row = &self.header_row;
+        let format = self.format_for(path);
+
+        match self.target {
+            Route::Column(_) => if let Some(dirname) = path.parent() {
+                fs::create_dir_all(dirname).map_err(|e| Error::Io(dirname.to_path_buf(), e))?;
             },
-            FlushTarget::Path(ref path) => {
-                if self.targets.contains_key(path) {
-                    Ok(self.targets.get_mut(path).unwrap())
-                } else {
-                    let mut writer =
-                        Writer::from_path(path).expect("Could not write to file");
+            Route::Path(_) => {},
+            Route::Writer(ref mut writer) => {
+                // this target is never closed, so it is created only once
+                let writer = writer.take().ok_or_else(|| Error::Io(
+                    path.to_path_buf(),
+                    io::Error::other("the writer failed while writing the headers"),
+                ))?;
+                let mut target = Target {
+                    writer: RowWriter::new(format, Box::new(Stream(writer)), header_row),
+                    destination: Destination::stream(WRITER_LABEL),
+                    last_used: 0,
+                };
 
-                    writer
-                        .write_record(&header_row)
-                        .expect("Could not write headers");
+                target.writer.write_headers(header_row)
+                    .map_err(|e| Error::Write(path.to_path_buf(), e))?;
 
-                    self.targets.insert(path.to_path_buf(), writer);
+                return Ok(target);
+            },
+        }
 
-                    Ok(self.targets.get_mut(path).unwrap())
-                }
+        let append = match (self.policy, format) {
+            // JSON Lines files have no headers to check
+            (WritePolicy::Append, OutputFormat::JsonLines) => fs::metadata(path).map(|m| m.len() > 0).unwrap_or(false),
+            (WritePolicy::Append, OutputFormat::Csv) => match read_headers(path) {
+                Ok(Some(ref headers)) if headers == header_row => true,
+                Ok(Some(_)) => return Err(Error::HeaderMismatch(path.to_path_buf())),
+                Ok(None) => false,
+                Err(e) => return Err(Error::Io(path.to_path_buf(), e)),
             },
+            _ => false,
+        };
+        let mut target = Target::create(path, self.atomic, self.policy, append, format, header_row)?;
+
+        if append {
+            return Ok(target);
+        }
+
+        if let Err(e) = target.writer.write_headers(header_row) {
+            target.destination.discard();
+
+            return Err(Error::Write(path.to_path_buf(), e));
+        }
+
+        Ok(target)
+    }
+
+    /// Makes room for a new target if the limit of open files was reached
+    fn close_least_recently_used(&mut self) {
+        let max_open_files = match self.max_open_files {
+            Some(max) => max,
+            None => return,
+        };
+
+        while self.targets.len() >= max_open_files.max(1) {
+            let path = self.targets.iter()
+                .min_by_key(|(_, target)| target.last_used)
+                .map(|(path, _)| path.clone())
+                .unwrap();
+            let target = self.targets.remove(&path).unwrap();
+
+            match target.close() {
+                Ok(destination) => {
+                    self.closed.insert(path, destination);
+                },
+                Err(e) => self.errors.push_back(e),
+            }
+        }
+    }
+
+    /// Finishes every target once the stream is over
+    fn finish(&mut self) {
+        for (_, target) in self.targets.drain() {
+            if let Err(e) = target.finish() {
+                self.errors.push_back(e);
+            }
+        }
+
+        for (path, destination) in self.closed.drain() {
+            if let Err(e) = destination.commit() {
+                self.errors.push_back(Error::Io(path, e));
+            }
         }
+
+        self.finished = true;
     }
 }
 
@@ -127,20 +414,49 @@ where
     type Item = RowResult;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.errors.pop_front() {
+            return Some(Err(e));
+        }
+
         match self.iter.next() {
             Some(Ok(row)) => {
                 let trimmed_row = trim_underscores(&self.headers, &row);
 
                 match self.get_target(&row) {
-                    Ok(target) => match target.write_record(&trimmed_row) {
+                    Ok(target) => match target.writer.write_row(&trimmed_row) {
                         Ok(_) => Some(Ok(row)),
-                        Err(e) => Some(Err(Error::Csv(e))),
+                        Err(e) => Some(Err(Error::Write(target.destination.path().to_path_buf(), e))),
                     },
                     Err(err) => Some(Err(err)),
                 }
             },
             err @ Some(Err(_)) => err,
-            None => None,
+            None => {
+                if !self.finished {
+                    self.finish();
+                }
+
+                self.errors.pop_front().map(Err)
+            },
+        }
+    }
+}
+
+impl<I> Drop for IntoIter<I> {
+    fn drop(&mut self) {
+        // the stream was not consumed, so atomic targets are incomplete
+        for (_, target) in self.targets.drain() {
+            if self.atomic {
+                target.destination.discard();
+            } else {
+                target.finish().ok();
+            }
+        }
+
+        for (_, destination) in self.closed.drain() {
+            if self.atomic {
+                destination.discard();
+            }
         }
     }
 }
@@ -156,9 +472,426 @@ where
     fn into_iter(self) -> Self::IntoIter {
         IntoIter {
             targets: HashMap::new(),
-            target: self.target,
+            closed: HashMap::new(),
+            target: Route::from(self.target),
+            atomic: self.atomic,
+            max_open_files: self.max_open_files,
+            policy: self.policy,
+            format: self.format,
+            uses: 0,
+            header_row: trim_header_underscores(self.iter.headers().as_row()),
             headers: self.iter.headers().clone(),
             iter: self.iter.into_iter(),
+            errors: VecDeque::new(),
+            finished: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::{self, Write};
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::sync::{Arc, Mutex};
+
+    use super::{Flush, FlushTarget, Row, WritePolicy, output::ON_OPEN};
+    use crate::{mock::MockStream, temp::TempDir, error::Error};
+
+    fn stream() -> MockStream<std::vec::IntoIter<crate::RowResult>> {
+        MockStream::from_rows(
+            vec![
+                Ok(Row::from(vec!["a", "_b"])),
+                Ok(Row::from(vec!["1", "x"])),
+                Ok(Row::from(vec!["2", "y"])),
+            ]
+            .into_iter(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_flush_atomic() {
+        let dir = TempDir::new("csvsc-test").unwrap();
+        let path = dir.path().join("out.csv");
+
+        let mut flush = Flush::new(stream(), FlushTarget::Path(path.clone())).unwrap()
+            .with_atomic_writes()
+            .into_iter();
+
+        flush.next().unwrap().unwrap();
+        assert!(!path.exists());
+
+        flush.next().unwrap().unwrap();
+        assert!(flush.next().is_none());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a\n1\n2\n");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_flush_atomic_unfinished() {
+        let dir = TempDir::new("csvsc-test").unwrap();
+        let path = dir.path().join("out.csv");
+
+        {
+            let mut flush = Flush::new(stream(), FlushTarget::Path(path.clone())).unwrap()
+                .with_atomic_writes()
+                .into_iter();
+
+            flush.next().unwrap().unwrap();
         }
+
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_flush_errors_keep_the_stream_flowing() {
+        let dir = TempDir::new("csvsc-test").unwrap();
+        let blocker = dir.path().join("file");
+        let ok = dir.path().join("ok.csv");
+
+        fs::write(&blocker, "").unwrap();
+
+        let iter = MockStream::from_rows(
+            vec![
+                Ok(Row::from(vec!["a", "_target"])),
+                Ok(Row::from(vec!["1", &blocker.join("out.csv").to_string_lossy()])),
+                Ok(Row::from(vec!["2", &ok.to_string_lossy()])),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+
+        let mut flush = Flush::new(iter, FlushTarget::Column("_target".to_string())).unwrap()
+            .into_iter();
+
+        match flush.next() {
+            Some(Err(Error::Io(path, _))) => assert_eq!(path, blocker),
+            x => unreachable!("{:?}", x),
+        }
+
+        flush.next().unwrap().unwrap();
+        assert!(flush.next().is_none());
+        assert_eq!(fs::read_to_string(&ok).unwrap(), "a\n2\n");
+    }
+
+    #[test]
+    fn test_flush_max_open_files() {
+        let dir = TempDir::new("csvsc-test").unwrap();
+        let a = dir.path().join("a.csv");
+        let b = dir.path().join("b.csv");
+
+        let iter = MockStream::from_rows(
+            vec![
+                Ok(Row::from(vec!["a", "_target"])),
+                Ok(Row::from(vec!["1", &a.to_string_lossy()])),
+                Ok(Row::from(vec!["2", &b.to_string_lossy()])),
+                Ok(Row::from(vec!["3", &a.to_string_lossy()])),
+                Ok(Row::from(vec!["4", &b.to_string_lossy()])),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+
+        let flush = Flush::new(iter, FlushTarget::Column("_target".to_string())).unwrap()
+            .with_atomic_writes()
+            .with_max_open_files(1);
+
+        for result in flush {
+            result.unwrap();
+        }
+
+        assert_eq!(fs::read_to_string(&a).unwrap(), "a\n1\n3\n");
+        assert_eq!(fs::read_to_string(&b).unwrap(), "a\n2\n4\n");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_flush_max_open_files_is_never_exceeded() {
+        let dir = TempDir::new("csvsc-test").unwrap();
+        let max_open = Rc::new(Cell::new(0));
+        let watched = dir.path().canonicalize().unwrap();
+        let max = max_open.clone();
+
+        // the files of the directory open by this process, counted every time
+        // one is opened
+        ON_OPEN.with(|hook| *hook.borrow_mut() = Some(Box::new(move |_path: &std::path::Path| {
+            let open = fs::read_dir("/proc/self/fd").unwrap()
+                .filter_map(|entry| fs::read_link(entry.ok()?.path()).ok())
+                .filter(|target| target.starts_with(&watched))
+                .count();
+
+            max.set(max.get().max(open));
+        })));
+
+        let paths: Vec<String> = ["a.csv", "b.csv", "c.csv"].iter()
+            .map(|name| dir.path().join(name).to_string_lossy().to_string())
+            .collect();
+        let mut rows = vec![Ok(Row::from(vec!["a", "_target"]))];
+
+        for i in 0..9 {
+            rows.push(Ok(Row::from(vec![i.to_string().as_str(), paths[i % 3].as_str()])));
+        }
+
+        let flush = Flush::new(MockStream::from_rows(rows.into_iter()).unwrap(), FlushTarget::Column("_target".to_string())).unwrap()
+            .with_max_open_files(2);
+
+        for result in flush {
+            result.unwrap();
+        }
+
+        ON_OPEN.with(|hook| *hook.borrow_mut() = None);
+
+        assert_eq!(max_open.get(), 2);
+        assert_eq!(fs::read_to_string(&paths[0]).unwrap(), "a\n0\n3\n6\n");
+    }
+
+    #[test]
+    fn test_flush_writer() {
+        struct Shared(Arc<Mutex<Vec<u8>>>);
+
+        impl Write for Shared {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        let flush = Flush::new(stream(), FlushTarget::Writer(Box::new(Shared(buffer.clone())))).unwrap();
+
+        for result in flush {
+            result.unwrap();
+        }
+
+        assert_eq!(&*buffer.lock().unwrap(), b"a\n1\n2\n");
+    }
+
+    #[test]
+    fn test_flush_writer_failing_on_headers() {
+        struct Broken;
+
+        impl Write for Broken {
+            fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+                Err(io::Error::new(io::ErrorKind::BrokenPipe, "broken pipe"))
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        // big enough to go past the buffer of the writer
+        let header = "h".repeat(10_000);
+        let iter = MockStream::from_rows(
+            vec![
+                Ok(Row::from(vec![header.as_str()])),
+                Ok(Row::from(vec!["1"])),
+                Ok(Row::from(vec!["2"])),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+
+        let mut flush = Flush::new(iter, FlushTarget::Writer(Box::new(Broken))).unwrap().into_iter();
+
+        match flush.next() {
+            Some(Err(Error::Write(..))) => {},
+            x => unreachable!("{:?}", x),
+        }
+
+        match flush.next() {
+            Some(Err(Error::Io(..))) => {},
+            x => unreachable!("{:?}", x),
+        }
+
+        assert!(flush.next().is_none());
+    }
+
+    #[test]
+    fn test_flush_json_lines() {
+        let dir = TempDir::new("csvsc-test").unwrap();
+        let csv = dir.path().join("a.csv");
+        let json = dir.path().join("a.jsonl");
+
+        let iter = MockStream::from_rows(
+            vec![
+                Ok(Row::from(vec!["name", "quote", "_target"])),
+                Ok(Row::from(vec!["a", "say \"hi\"", &csv.to_string_lossy()])),
+                Ok(Row::from(vec!["b", "ñ", &json.to_string_lossy()])),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+
+        for result in Flush::new(iter, FlushTarget::Column("_target".to_string())).unwrap() {
+            result.unwrap();
+        }
+
+        assert_eq!(fs::read_to_string(&csv).unwrap(), "name,quote\na,\"say \"\"hi\"\"\"\n");
+        assert_eq!(fs::read_to_string(&json).unwrap(), "{\"name\":\"b\",\"quote\":\"ñ\"}\n");
+    }
+
+    #[test]
+    fn test_flush_append() {
+        let dir = TempDir::new("csvsc-test").unwrap();
+        let path = dir.path().join("out.csv");
+
+        fs::write(&path, "a\n0\n").unwrap();
+
+        for atomic in [false, true].iter() {
+            let mut flush = Flush::new(stream(), FlushTarget::Path(path.clone())).unwrap()
+                .with_policy(WritePolicy::Append);
+
+            if *atomic {
+                flush = flush.with_atomic_writes();
+            }
+
+            for result in flush {
+                result.unwrap();
+            }
+        }
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a\n0\n1\n2\n1\n2\n");
+    }
+
+    #[test]
+    fn test_flush_append_without_final_newline() {
+        let dir = TempDir::new("csvsc-test").unwrap();
+
+        for atomic in [false, true].iter() {
+            let path = dir.path().join(format!("out-{}.csv", atomic));
+
+            fs::write(&path, "a\n0").unwrap();
+
+            let mut flush = Flush::new(stream(), FlushTarget::Path(path.clone())).unwrap()
+                .with_policy(WritePolicy::Append);
+
+            if *atomic {
+                flush = flush.with_atomic_writes();
+            }
+
+            for result in flush {
+                result.unwrap();
+            }
+
+            assert_eq!(fs::read_to_string(&path).unwrap(), "a\n0\n1\n2\n");
+        }
+    }
+
+    #[test]
+    fn test_flush_append_header_mismatch() {
+        let dir = TempDir::new("csvsc-test").unwrap();
+        let path = dir.path().join("out.csv");
+
+        fs::write(&path, "b\n0\n").unwrap();
+
+        let mut flush = Flush::new(stream(), FlushTarget::Path(path.clone())).unwrap()
+            .with_policy(WritePolicy::Append)
+            .into_iter();
+
+        match flush.next() {
+            Some(Err(Error::HeaderMismatch(p))) => assert_eq!(p, path),
+            x => unreachable!("{:?}", x),
+        }
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "b\n0\n");
+    }
+
+    #[test]
+    fn test_flush_fail_if_exists() {
+        let dir = TempDir::new("csvsc-test").unwrap();
+        let path = dir.path().join("out.csv");
+
+        fs::write(&path, "a\n0\n").unwrap();
+
+        for atomic in [false, true].iter() {
+            let mut flush = Flush::new(stream(), FlushTarget::Path(path.clone())).unwrap()
+                .with_policy(WritePolicy::FailIfExists);
+
+            if *atomic {
+                flush = flush.with_atomic_writes();
+            }
+
+            match flush.into_iter().next() {
+                Some(Err(Error::Io(p, _))) => assert_eq!(p, path),
+                x => unreachable!("{:?}", x),
+            }
+        }
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a\n0\n");
+    }
+
+    #[test]
+    fn test_flush_fail_if_exists_created_while_writing() {
+        let dir = TempDir::new("csvsc-test").unwrap();
+        let path = dir.path().join("out.csv");
+
+        let mut flush = Flush::new(stream(), FlushTarget::Path(path.clone())).unwrap()
+            .with_policy(WritePolicy::FailIfExists)
+            .with_atomic_writes()
+            .into_iter();
+
+        flush.next().unwrap().unwrap();
+        fs::write(&path, "other\n").unwrap();
+        flush.next().unwrap().unwrap();
+
+        match flush.next() {
+            Some(Err(Error::Io(p, _))) => assert_eq!(p, path),
+            x => unreachable!("{:?}", x),
+        }
+
+        assert!(flush.next().is_none());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "other\n");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    #[cfg(not(feature = "gzip"))]
+    fn test_flush_gzip_not_enabled_leaves_file_untouched() {
+        let dir = TempDir::new("csvsc-test").unwrap();
+        let path = dir.path().join("out.csv.gz");
+
+        fs::write(&path, "keep").unwrap();
+
+        for atomic in [false, true].iter() {
+            let mut flush = Flush::new(stream(), FlushTarget::Path(path.clone())).unwrap();
+
+            if *atomic {
+                flush = flush.with_atomic_writes();
+            }
+
+            match flush.into_iter().next() {
+                Some(Err(Error::Io(p, _))) => assert_eq!(p, path),
+                x => unreachable!("{:?}", x),
+            }
+        }
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "keep");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn test_flush_gzip() {
+        use crate::{InputStream, ReaderSource};
+        use encoding::all::UTF_8;
+
+        let dir = TempDir::new("csvsc-test").unwrap();
+        let path = dir.path().join("out.csv.gz");
+
+        for result in Flush::new(stream(), FlushTarget::Path(path.clone())).unwrap() {
+            result.unwrap();
+        }
+
+        let input = InputStream::from_readers(vec![ReaderSource::from_path(&path).unwrap()], UTF_8);
+        let rows: Vec<Row> = input.map(|r| r.unwrap()).collect();
+
+        assert_eq!(rows[1].get(0), Some("2"));
     }
 }
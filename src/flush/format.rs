@@ -0,0 +1,115 @@
+//! Formats in which rows can be flushed
+use csv::Writer;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use super::output::Output;
+use crate::Row;
+
+/// Format of the files written by a [Flush](struct.Flush.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// Comma separated values with a header line.
+    #[default]
+    Csv,
+
+    /// One JSON object per line, keyed by the names of the headers. Values
+    /// are always strings.
+    JsonLines,
+}
+
+impl OutputFormat {
+    /// Picks the format from the extension of the path, ignoring compression
+    /// extensions. Returns `None` if the extension doesn't tell.
+    pub fn from_path(path: &Path) -> Option<OutputFormat> {
+        let path = match path.extension().and_then(|e| e.to_str()) {
+            Some("gz") | Some("zst") => Path::new(path.file_stem()?),
+            _ => path,
+        };
+
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("csv") => Some(OutputFormat::Csv),
+            Some("jsonl") | Some("ndjson") => Some(OutputFormat::JsonLines),
+            _ => None,
+        }
+    }
+}
+
+/// Writes rows in the chosen format
+pub enum RowWriter {
+    Csv(Box<Writer<Box<dyn Output>>>),
+    JsonLines {
+        writer: BufWriter<Box<dyn Output>>,
+        keys: Vec<String>,
+    },
+}
+
+impl RowWriter {
+    pub fn new(format: OutputFormat, output: Box<dyn Output>, headers: &Row) -> RowWriter {
+        match format {
+            OutputFormat::Csv => RowWriter::Csv(Box::new(Writer::from_writer(output))),
+            OutputFormat::JsonLines => RowWriter::JsonLines {
+                writer: BufWriter::new(output),
+                keys: headers.iter().map(json_string).collect(),
+            },
+        }
+    }
+
+    /// Writes the header line, if the format has one
+    pub fn write_headers(&mut self, headers: &Row) -> csv::Result<()> {
+        match self {
+            RowWriter::Csv(writer) => writer.write_record(headers),
+            RowWriter::JsonLines { .. } => Ok(()),
+        }
+    }
+
+    pub fn write_row(&mut self, row: &Row) -> csv::Result<()> {
+        match self {
+            RowWriter::Csv(writer) => writer.write_record(row),
+            RowWriter::JsonLines { writer, keys } => {
+                let mut line = String::from("{");
+
+                for (i, (key, value)) in keys.iter().zip(row.iter()).enumerate() {
+                    if i > 0 {
+                        line.push(',');
+                    }
+
+                    line.push_str(key);
+                    line.push(':');
+                    line.push_str(&json_string(value));
+                }
+
+                line.push_str("}\n");
+
+                Ok(writer.write_all(line.as_bytes())?)
+            },
+        }
+    }
+
+    /// Flushes what is left and returns the underlying output
+    pub fn into_inner(self) -> io::Result<Box<dyn Output>> {
+        match self {
+            RowWriter::Csv(writer) => writer.into_inner().map_err(|e| e.into_error()),
+            RowWriter::JsonLines { writer, .. } => writer.into_inner().map_err(|e| e.into_error()),
+        }
+    }
+}
+
+fn json_string(value: &str) -> String {
+    serde_json::to_string(value).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::OutputFormat;
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(OutputFormat::from_path(Path::new("a/b.csv")), Some(OutputFormat::Csv));
+        assert_eq!(OutputFormat::from_path(Path::new("b.jsonl.gz")), Some(OutputFormat::JsonLines));
+        assert_eq!(OutputFormat::from_path(Path::new("b.ndjson")), Some(OutputFormat::JsonLines));
+        assert_eq!(OutputFormat::from_path(Path::new("b.txt")), None);
+    }
+}
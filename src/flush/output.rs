@@ -0,0 +1,303 @@
+//! Files written by a flush, optionally compressed and written atomically
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use super::WritePolicy;
+use crate::{Row, input::decompress};
+
+/// A destination of rows that needs to be finished once everything is
+/// written, e.g. to write the trailer of a compressed file. It must be `Send`
+/// so a `Flush` can be moved to another thread.
+pub trait Output: Write + Send {
+    fn finish(self: Box<Self>) -> io::Result<()>;
+}
+
+impl Output for File {
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Any writer given by the user, like the standard output
+pub struct Stream(pub Box<dyn Write + Send>);
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl Output for Stream {
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl Output for flate2::write::GzEncoder<File> {
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        (*self).finish().map(|_| ())
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl Output for zstd::stream::write::Encoder<'static, File> {
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        (*self).finish().map(|_| ())
+    }
+}
+
+/// Fails if the extension of the path asks for a compression whose cargo
+/// feature is not enabled. Checked before touching the file.
+fn check_compression(path: &Path) -> io::Result<()> {
+    match path.extension().and_then(|e| e.to_str()) {
+        #[cfg(not(feature = "gzip"))]
+        Some("gz") => Err(not_enabled("gzip")),
+        #[cfg(not(feature = "zstd"))]
+        Some("zst") => Err(not_enabled("zstd")),
+        _ => Ok(()),
+    }
+}
+
+/// Wraps the file in an encoder if the extension of the path asks for
+/// compression. The extension must have passed `check_compression`.
+fn compress(file: File, path: &Path) -> io::Result<Box<dyn Output>> {
+    opened(path);
+
+    match path.extension().and_then(|e| e.to_str()) {
+        #[cfg(feature = "gzip")]
+        Some("gz") => Ok(Box::new(flate2::write::GzEncoder::new(file, flate2::Compression::default()))),
+
+        #[cfg(feature = "zstd")]
+        Some("zst") => Ok(Box::new(zstd::stream::write::Encoder::new(file, 0)?)),
+
+        _ => Ok(Box::new(file)),
+    }
+}
+
+#[cfg(test)]
+type OpenHook = Option<Box<dyn Fn(&Path)>>;
+
+#[cfg(test)]
+thread_local! {
+    /// Called with the path of every file opened for writing by this thread,
+    /// so tests can watch them
+    pub static ON_OPEN: std::cell::RefCell<OpenHook> = const { std::cell::RefCell::new(None) };
+}
+
+#[cfg(test)]
+fn opened(path: &Path) {
+    ON_OPEN.with(|hook| if let Some(hook) = hook.borrow().as_ref() {
+        hook(path)
+    });
+}
+
+#[cfg(not(test))]
+fn opened(_path: &Path) {}
+
+#[cfg(not(all(feature = "gzip", feature = "zstd")))]
+fn not_enabled(feature: &str) -> io::Error {
+    io::Error::other(format!("writing this file requires the `{}` feature of csvsc", feature))
+}
+
+/// The temporary file used while writing the given path atomically. It lives
+/// in the same directory so it can be renamed.
+fn temp_path(path: &Path) -> PathBuf {
+    let filename = path.file_name().map(|f| f.to_string_lossy()).unwrap_or_default();
+
+    path.with_file_name(format!(".{}.{}.tmp", filename, process::id()))
+}
+
+/// Reads the headers of an existing file. Returns `None` if the file does not
+/// exist or is empty.
+pub fn read_headers(path: &Path) -> io::Result<Option<Row>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(decompress(file, path)?);
+
+    match reader.records().next() {
+        Some(Ok(row)) => Ok(Some(row)),
+        Some(Err(e)) => Err(e.into()),
+        None => Ok(None),
+    }
+}
+
+/// Whether the given file is empty or ends with a line terminator, so that
+/// rows can be appended to it. Compressed files have to be decompressed
+/// whole to tell.
+fn ends_with_newline(path: &Path) -> io::Result<bool> {
+    let mut file = File::open(path)?;
+    let mut last = [b'\n'];
+
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("gz") | Some("zst") => {
+            let mut read = decompress(file, path)?;
+            let mut buffer = [0; 8192];
+
+            loop {
+                match read.read(&mut buffer)? {
+                    0 => break,
+                    n => last[0] = buffer[n - 1],
+                }
+            }
+        },
+        _ => if file.metadata()?.len() > 0 {
+            file.seek(SeekFrom::End(-1))?;
+            file.read_exact(&mut last)?;
+        },
+    }
+
+    Ok(last[0] == b'\n')
+}
+
+/// Where the writer of a target is actually writing to
+pub struct Destination {
+    path: PathBuf,
+    temp: Option<PathBuf>,
+    /// Fail on commit if the path exists instead of replacing it
+    no_clobber: bool,
+}
+
+impl Destination {
+    /// Opens the given path for writing as the policy says. If `append` is
+    /// true the existing contents of the file are kept. If `atomic` is true a
+    /// temporary file is written instead and renamed to the path when
+    /// finished.
+    pub fn create(path: &Path, atomic: bool, policy: WritePolicy, append: bool) -> io::Result<(Destination, Box<dyn Output>)> {
+        check_compression(path)?;
+
+        if atomic && policy == WritePolicy::FailIfExists && path.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "the target file already exists",
+            ));
+        }
+
+        let temp = if atomic {
+            Some(temp_path(path))
+        } else {
+            None
+        };
+
+        let destination = Destination {
+            path: path.to_path_buf(),
+            temp,
+            no_clobber: policy == WritePolicy::FailIfExists,
+        };
+
+        match destination.open(policy, append) {
+            Ok(output) => Ok((destination, output)),
+            Err(e) => {
+                destination.discard();
+
+                Err(e)
+            },
+        }
+    }
+
+    fn open(&self, policy: WritePolicy, append: bool) -> io::Result<Box<dyn Output>> {
+        let newline = append && !ends_with_newline(&self.path)?;
+
+        if append {
+            if let Some(ref temp) = self.temp {
+                fs::copy(&self.path, temp)?;
+            }
+        }
+
+        let file = OpenOptions::new()
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .create(true)
+            .create_new(self.temp.is_none() && policy == WritePolicy::FailIfExists)
+            .open(self.temp.as_deref().unwrap_or(&self.path))?;
+        let mut output = compress(file, &self.path)?;
+
+        // otherwise the first row would be glued to the last one
+        if newline {
+            output.write_all(b"\n")?;
+        }
+
+        Ok(output)
+    }
+
+    /// A destination that is not a file, identified by the given label
+    pub fn stream(label: &str) -> Destination {
+        Destination {
+            path: PathBuf::from(label),
+            temp: None,
+            no_clobber: false,
+        }
+    }
+
+    /// Opens the output again, after it was finished, to add more data at
+    /// the end
+    pub fn reopen(&self) -> io::Result<Box<dyn Output>> {
+        let file = OpenOptions::new()
+            .append(true)
+            .open(self.temp.as_deref().unwrap_or(&self.path))?;
+
+        compress(file, &self.path)
+    }
+
+    /// The final path of the output
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Moves the temporary file, if any, to its final location, syncing it
+    /// first so the renamed file is never seen empty after a crash. Must be
+    /// called after the output is finished.
+    ///
+    /// If the path must not be replaced the file is hard linked to it, which
+    /// fails if the path was created in the meantime. On filesystems without
+    /// hard links the path is checked right before renaming instead, leaving
+    /// a small window in which a file created by someone else is replaced.
+    pub fn commit(self) -> io::Result<()> {
+        let temp = match self.temp {
+            Some(ref temp) => temp,
+            None => return Ok(()),
+        };
+
+        let result = OpenOptions::new().write(true).open(temp)
+            .and_then(|file| file.sync_all())
+            .and_then(|_| if self.no_clobber {
+                match fs::hard_link(temp, &self.path) {
+                    Ok(()) => fs::remove_file(temp),
+                    Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Err(e),
+                    Err(_) if self.path.exists() => Err(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        "the target file already exists",
+                    )),
+                    Err(_) => fs::rename(temp, &self.path),
+                }
+            } else {
+                fs::rename(temp, &self.path)
+            });
+
+        if result.is_err() {
+            fs::remove_file(temp).ok();
+        }
+
+        result
+    }
+
+    /// Removes the temporary file, if any, of an output that won't be
+    /// finished.
+    pub fn discard(self) {
+        if let Some(ref temp) = self.temp {
+            fs::remove_file(temp).ok();
+        }
+    }
+}
@@ -1,26 +1,109 @@
 use std::vec;
-use std::collections::{HashMap, hash_map};
+use std::collections::{HashMap, hash_map::RandomState};
+use std::hash::BuildHasher;
 use crate::{
-    RowStream, Headers, RowResult, GroupBuildError,
+    RowStream, Headers, Row, RowResult, GroupBuildError,
     mock::MockStream,
     error::Error,
 };
 
+mod runs;
+mod spill;
+
+use runs::{MergedGroups, OrderedSpill};
+use spill::{Spill, Partitions};
+
 /// Groups data by a set of columns.
 ///
 /// The groups are passed to you as a RowStream
 /// object that you can use to manipulate them. Data is group globally from the
 /// stream so if the stream is huge you'll have all the rows in memory at the
-/// same time.
+/// same time, unless a memory budget is set with
+/// [with_memory_budget](#method.with_memory_budget).
+///
+/// Rows are grouped by the actual values of the grouping columns, so two rows
+/// end up in the same group only if those values are equal.
+///
+/// The order in which groups are emitted is given by a
+/// [GroupOrder](enum.GroupOrder.html), unordered by default.
 ///
 /// If you add or delete headers you're responsible for modifying the headers also,
 /// which will be given to you as a parameter of the first closure.
-pub struct Group<I, F> {
+pub struct Group<I, F, S = RandomState> {
     iter: I,
     f: F,
     headers: Headers,
     old_headers: Headers,
     group_by: Vec<String>,
+    hash_builder: S,
+    order: GroupOrder,
+    memory_budget: Option<usize>,
+}
+
+/// Order in which a [Group](struct.Group.html) emits its groups.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GroupOrder {
+    /// No particular order, it may change between runs.
+    #[default]
+    Unordered,
+
+    /// Groups are emitted in the order their first row was found in the
+    /// stream.
+    FirstSeen,
+
+    /// Groups are emitted sorted by the values of the grouping columns,
+    /// compared as strings. Errors found in the stream come last.
+    Sorted,
+}
+
+/// Identifies the group of rows handed to a closure.
+#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum GroupKey {
+    /// Rows that share these values of the grouping columns, given in the
+    /// same order as the columns.
+    Rows(Vec<String>),
+
+    /// The errors found in the stream.
+    Errors,
+}
+
+/// Handles each group found by a [Group](struct.Group.html) or an
+/// [AdjacentGroup](struct.AdjacentGroup.html).
+///
+/// You don't need to implement this trait, it is implemented for closures
+/// that receive only the rows of the group and, through
+/// [WithKey](struct.WithKey.html), for closures that also receive its key.
+pub trait GroupHandler {
+    type Output: RowStream;
+
+    fn handle(&mut self, key: &GroupKey, rows: MockStream<vec::IntoIter<RowResult>>) -> Self::Output;
+}
+
+impl<F, R> GroupHandler for F
+where
+    F: FnMut(MockStream<vec::IntoIter<RowResult>>) -> R,
+    R: RowStream,
+{
+    type Output = R;
+
+    fn handle(&mut self, _key: &GroupKey, rows: MockStream<vec::IntoIter<RowResult>>) -> R {
+        (self)(rows)
+    }
+}
+
+/// Wraps a closure that receives the key of the group along with its rows.
+pub struct WithKey<F>(pub F);
+
+impl<F, R> GroupHandler for WithKey<F>
+where
+    F: FnMut(&GroupKey, MockStream<vec::IntoIter<RowResult>>) -> R,
+    R: RowStream,
+{
+    type Output = R;
+
+    fn handle(&mut self, key: &GroupKey, rows: MockStream<vec::IntoIter<RowResult>>) -> R {
+        (self.0)(key, rows)
+    }
 }
 
 impl<I, F, R> Group<I, F>
@@ -30,6 +113,45 @@ where
     R: RowStream,
 {
     pub fn new<H>(
+        iter: I,
+        header_map: H,
+        f: F,
+        grouping: &[&str],
+    ) -> Result<Group<I, F>, GroupBuildError>
+    where
+        H: FnMut(Headers) -> Headers,
+    {
+        Group::build(iter, header_map, f, grouping)
+    }
+}
+
+impl<I, F, R> Group<I, WithKey<F>>
+where
+    I: RowStream,
+    F: FnMut(&GroupKey, MockStream<vec::IntoIter<RowResult>>) -> R,
+    R: RowStream,
+{
+    /// Like [new](#method.new) but the closure also receives the key of each
+    /// group, which tells the values of the grouping columns or if the group
+    /// holds the errors found in the stream.
+    pub fn new_with_key<H>(
+        iter: I,
+        header_map: H,
+        f: F,
+        grouping: &[&str],
+    ) -> Result<Group<I, WithKey<F>>, GroupBuildError>
+    where
+        H: FnMut(Headers) -> Headers,
+    {
+        Group::build(iter, header_map, WithKey(f), grouping)
+    }
+}
+
+impl<I, F> Group<I, F>
+where
+    I: RowStream,
+{
+    fn build<H>(
         iter: I,
         mut header_map: H,
         f: F,
@@ -58,32 +180,135 @@ where
             headers,
             old_headers,
             group_by,
+            hash_builder: RandomState::new(),
+            order: GroupOrder::default(),
+            memory_budget: None,
         })
     }
 }
 
-#[derive(Hash,PartialEq,Eq)]
-enum GroupKey {
-    Rows(u64),
-    Errors,
+impl<I, F, S> Group<I, F, S> {
+    /// Uses the given hash builder for the map that holds the groups while
+    /// they are being collected.
+    pub fn with_hasher<T: BuildHasher>(self, hash_builder: T) -> Group<I, F, T> {
+        Group {
+            iter: self.iter,
+            f: self.f,
+            headers: self.headers,
+            old_headers: self.old_headers,
+            group_by: self.group_by,
+            hash_builder,
+            order: self.order,
+            memory_budget: self.memory_budget,
+        }
+    }
+
+    /// Sets the order in which the groups will be emitted.
+    pub fn with_order(self, order: GroupOrder) -> Group<I, F, S> {
+        Group {
+            order,
+            ..self
+        }
+    }
+
+    /// Limits the amount of row data, in bytes, that is kept in memory while
+    /// collecting the groups. Once the limit is exceeded rows are spilled to
+    /// temporary CSV files partitioned by their grouping key, and each
+    /// partition is read back and grouped on its own. Partitions that are
+    /// still bigger than the budget are partitioned again, so only a single
+    /// group bigger than the budget is ever loaded over it.
+    ///
+    /// If the groups must be emitted in some
+    /// [GroupOrder](enum.GroupOrder.html) rows are instead written to sorted
+    /// runs that are merged at the end, so the order holds. With
+    /// `GroupOrder::FirstSeen` the keys seen so far are kept in memory to
+    /// tell their order.
+    pub fn with_memory_budget(self, bytes: usize) -> Group<I, F, S> {
+        Group {
+            memory_budget: Some(bytes),
+            ..self
+        }
+    }
 }
 
-pub struct IntoIter<F, R>
+/// Groups being collected. Besides the rows each group keeps the position in
+/// which it was first seen, used for ordering.
+type Groups<S> = HashMap<GroupKey, (usize, Vec<RowResult>), S>;
+
+fn push_row<S: BuildHasher>(groups: &mut Groups<S>, key: GroupKey, item: RowResult) {
+    let next_index = groups.len();
+
+    groups
+        .entry(key)
+        .or_insert_with(|| (next_index, vec![]))
+        .1
+        .push(item);
+}
+
+fn key_row(headers: &Headers, group_by: &[String], result: RowResult) -> Result<(Vec<String>, Row), Error> {
+    result.and_then(|item| {
+        match headers.key(&item, group_by) {
+            Ok(key) => Ok((key, item)),
+            Err(col) => Err(Error::ColumnNotFound(col)),
+        }
+    })
+}
+
+fn ordered<S>(groups: Groups<S>, order: GroupOrder) -> vec::IntoIter<(GroupKey, (usize, Vec<RowResult>))> {
+    let mut groups: Vec<_> = groups.into_iter().collect();
+
+    match order {
+        GroupOrder::Unordered => {},
+        GroupOrder::FirstSeen => groups.sort_by_key(|(_, (index, _))| *index),
+        GroupOrder::Sorted => groups.sort_by(|(a, _), (b, _)| a.cmp(b)),
+    }
+
+    groups.into_iter()
+}
+
+/// Moves every group of rows currently in memory to a new spill, leaving only
+/// the errors behind
+fn spill_groups<S: BuildHasher>(groups: &mut Groups<S>) -> csv::Result<Spill> {
+    let mut spill = Spill::new()?;
+    let mut errors = vec![];
+
+    for (key, (_, items)) in groups.drain() {
+        match key {
+            // groups of rows only contain Ok items
+            GroupKey::Rows(key) => for row in items.into_iter().flatten() {
+                if let Err(e) = spill.write(&key, &row) {
+                    errors.push(Err(Error::Csv(e)));
+                }
+            },
+            GroupKey::Errors => errors.extend(items),
+        }
+    }
+
+    for error in errors {
+        push_row(groups, GroupKey::Errors, error);
+    }
+
+    Ok(spill)
+}
+
+pub struct IntoIter<F>
 where
-    F: FnMut(MockStream<vec::IntoIter<RowResult>>) -> R,
-    R: RowStream,
+    F: GroupHandler,
 {
-    iter: hash_map::IntoIter<GroupKey, Vec<RowResult>>,
+    iter: vec::IntoIter<(GroupKey, (usize, Vec<RowResult>))>,
+    partitions: Option<Partitions>,
+    merged: Option<MergedGroups>,
     f: F,
     headers: Headers,
     old_headers: Headers,
-    current_group: Option<R::IntoIter>,
+    group_by: Vec<String>,
+    order: GroupOrder,
+    current_group: Option<<F::Output as IntoIterator>::IntoIter>,
 }
 
-impl<F, R> Iterator for IntoIter<F, R>
+impl<F> Iterator for IntoIter<F>
 where
-    F: FnMut(MockStream<vec::IntoIter<RowResult>>) -> R,
-    R: RowStream,
+    F: GroupHandler,
 {
     type Item = RowResult;
 
@@ -98,10 +323,37 @@ where
                 },
             },
             None => match self.iter.next() {
-                None => None,
-                Some((_, vec)) => {
-                    let output_stream = (self.f)(
-                        MockStream::new(vec.into_iter(), self.old_headers.clone())
+                None if self.merged.is_some() => match self.merged.as_mut().and_then(|m| m.next()) {
+                    None => None,
+                    Some(Ok((key, rows))) => {
+                        self.iter = vec![(key, (0, rows))].into_iter();
+
+                        self.next()
+                    },
+                    Some(Err(e)) => Some(Err(Error::Csv(e))),
+                },
+                None => match self.partitions.as_mut().and_then(|p| p.next()) {
+                    None => None,
+                    Some(Ok(rows)) => {
+                        let mut groups = HashMap::new();
+
+                        for row in rows {
+                            match key_row(&self.old_headers, &self.group_by, Ok(row)) {
+                                Ok((key, item)) => push_row(&mut groups, GroupKey::Rows(key), Ok(item)),
+                                Err(error) => push_row(&mut groups, GroupKey::Errors, Err(error)),
+                            }
+                        }
+
+                        self.iter = ordered(groups, self.order);
+
+                        self.next()
+                    },
+                    Some(Err(e)) => Some(Err(Error::Csv(e))),
+                },
+                Some((key, (_, vec))) => {
+                    let output_stream = self.f.handle(
+                        &key,
+                        MockStream::new(vec.into_iter(), self.old_headers.clone()),
                     );
 
                     if *output_stream.headers() != self.headers {
@@ -117,54 +369,104 @@ where
     }
 }
 
-impl<I, F, R> IntoIterator for Group<I, F>
+impl<I, F, S> IntoIterator for Group<I, F, S>
 where
     I: RowStream,
-    F: FnMut(MockStream<vec::IntoIter<RowResult>>) -> R,
-    R: RowStream,
+    F: GroupHandler,
+    S: BuildHasher,
 {
     type Item = RowResult;
 
-    type IntoIter = IntoIter<F, R>;
+    type IntoIter = IntoIter<F>;
 
     fn into_iter(self) -> Self::IntoIter {
-        let mut groups = HashMap::new();
-        let iter = self.iter.into_iter();
-
-        for result in iter {
-            match result {
-                Ok(item) => {
-                    let row_hash = self.headers.hash(&item, &self.group_by).unwrap();
-
-                    groups
-                        .entry(GroupKey::Rows(row_hash))
-                        .or_insert(vec![])
-                        .push(Ok(item));
-                }
-                Err(error) => {
-                    groups
-                        .entry(GroupKey::Errors)
-                        .or_insert(vec![])
-                        .push(Err(error));
+        let mut groups = HashMap::with_hasher(self.hash_builder);
+        let mut spill: Option<Spill> = None;
+        let mut ordered_spill: Option<OrderedSpill> = None;
+        let mut memory_budget = self.memory_budget;
+        let mut buffered = 0;
+
+        for result in self.iter {
+            match key_row(&self.old_headers, &self.group_by, result) {
+                Ok((key, item)) => {
+                    if let Some(spill) = spill.as_mut() {
+                        if let Err(e) = spill.write(&key, &item) {
+                            push_row(&mut groups, GroupKey::Errors, Err(Error::Csv(e)));
+                        }
+
+                        continue;
+                    }
+
+                    buffered += item.as_slice().len();
+                    push_row(&mut groups, GroupKey::Rows(key), Ok(item));
+
+                    if !memory_budget.map(|budget| buffered > budget).unwrap_or(false) {
+                        continue;
+                    }
+
+                    let result = if self.order == GroupOrder::Unordered {
+                        spill_groups(&mut groups).map(|s| spill = Some(s))
+                    } else {
+                        let ordered = match ordered_spill {
+                            Some(ref mut ordered) => Ok(ordered),
+                            None => OrderedSpill::new(self.order, &self.group_by)
+                                .map(|ordered| ordered_spill.insert(ordered)),
+                        };
+
+                        ordered.and_then(|ordered| ordered.write(&mut groups))
+                    };
+
+                    match result {
+                        Ok(()) => buffered = 0,
+                        Err(e) => {
+                            // keep working in memory
+                            memory_budget = None;
+                            push_row(&mut groups, GroupKey::Errors, Err(Error::Csv(e)));
+                        },
+                    }
                 }
+                Err(error) => push_row(&mut groups, GroupKey::Errors, Err(error)),
             }
         }
 
+        let budget = self.memory_budget.unwrap_or(usize::MAX);
+        let (old_headers, group_by) = (&self.old_headers, &self.group_by);
+        let partitions = match spill.map(|s| s.into_partitions(budget, old_headers.clone(), group_by.clone())) {
+            Some(Ok(partitions)) => Some(partitions),
+            Some(Err(e)) => {
+                push_row(&mut groups, GroupKey::Errors, Err(Error::Csv(e)));
+
+                None
+            },
+            None => None,
+        };
+        let (iter, merged) = match ordered_spill {
+            Some(ordered_spill) => match ordered_spill.merge(groups, old_headers.clone(), group_by.clone()) {
+                Ok(merged) => (Vec::new().into_iter(), Some(merged)),
+                Err(e) => (vec![(GroupKey::Errors, (0, vec![Err(Error::Csv(e))]))].into_iter(), None),
+            },
+            None => (ordered(groups, self.order), None),
+        };
+
         IntoIter {
-            iter: groups.into_iter(),
+            iter,
+            partitions,
+            merged,
             f: self.f,
             headers: self.headers,
             old_headers: self.old_headers,
+            group_by: self.group_by,
+            order: self.order,
             current_group: None,
         }
     }
 }
 
-impl<I, F, R> RowStream for Group<I, F>
+impl<I, F, S> RowStream for Group<I, F, S>
 where
     I: RowStream,
-    F: FnMut(MockStream<vec::IntoIter<RowResult>>) -> R,
-    R: RowStream,
+    F: GroupHandler,
+    S: BuildHasher,
 {
     fn headers(&self) -> &Headers {
         &self.headers
@@ -180,7 +482,20 @@ mod tests {
         error::Error,
         ColSpec,
     };
-    use super::Group;
+    use super::{Group, GroupKey, GroupOrder};
+    use std::hash::{BuildHasherDefault, Hasher};
+
+    /// A hasher that makes every key collide
+    #[derive(Default)]
+    struct ConstantHasher;
+
+    impl Hasher for ConstantHasher {
+        fn finish(&self) -> u64 {
+            0
+        }
+
+        fn write(&mut self, _bytes: &[u8]) {}
+    }
 
     #[test]
     fn test_group() {
@@ -322,6 +637,307 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_group_hash_collision() {
+        let iter = MockStream::from_rows(
+            vec![
+                Ok(Row::from(vec!["name", "value"])),
+                Ok(Row::from(vec!["a", "1"])),
+                Ok(Row::from(vec!["b", "2"])),
+                Ok(Row::from(vec!["a", "3"])),
+                Ok(Row::from(vec!["c", "4"])),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+
+        let re = Group::new(iter, |headers| headers, |row_stream| {
+            let headers = row_stream.headers().clone();
+            let rows: Vec<_> = row_stream.into_iter().collect();
+            let names: Vec<_> = rows.iter().map(|row| {
+                headers.get_field(row.as_ref().unwrap(), "name").unwrap().to_string()
+            }).collect();
+
+            // every group must contain a single name
+            assert!(names.iter().all(|name| *name == names[0]));
+
+            MockStream::new(rows.into_iter(), headers)
+        }, &["name"]).unwrap().with_hasher(BuildHasherDefault::<ConstantHasher>::default());
+
+        let mut results: Vec<Row> = re.into_iter().map(|i| i.unwrap()).collect();
+
+        results.sort_by(|a, b| a.as_slice().cmp(b.as_slice()));
+
+        assert_eq!(
+            results,
+            vec![
+                Row::from(vec!["a", "1"]),
+                Row::from(vec!["a", "3"]),
+                Row::from(vec!["b", "2"]),
+                Row::from(vec!["c", "4"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_group_first_seen_order() {
+        let iter = MockStream::from_rows(
+            vec![
+                Ok(Row::from(vec!["name", "value"])),
+                Ok(Row::from(vec!["c", "1"])),
+                Ok(Row::from(vec!["a", "2"])),
+                Err(Error::InconsistentHeaders),
+                Ok(Row::from(vec!["c", "3"])),
+                Ok(Row::from(vec!["b", "4"])),
+                Ok(Row::from(vec!["a", "5"])),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+
+        let re = Group::new(iter, |headers| headers, |row_stream| row_stream, &["name"])
+            .unwrap()
+            .with_order(GroupOrder::FirstSeen);
+
+        let mut r = re.into_iter();
+
+        assert_eq!(r.next().unwrap().unwrap(), Row::from(vec!["c", "1"]));
+        assert_eq!(r.next().unwrap().unwrap(), Row::from(vec!["c", "3"]));
+        assert_eq!(r.next().unwrap().unwrap(), Row::from(vec!["a", "2"]));
+        assert_eq!(r.next().unwrap().unwrap(), Row::from(vec!["a", "5"]));
+
+        match r.next() {
+            Some(Err(Error::InconsistentHeaders)) => {},
+            _ => unreachable!(),
+        }
+
+        assert_eq!(r.next().unwrap().unwrap(), Row::from(vec!["b", "4"]));
+        assert!(r.next().is_none());
+    }
+
+    #[test]
+    fn test_group_sorted_order() {
+        let iter = MockStream::from_rows(
+            vec![
+                Ok(Row::from(vec!["name", "value"])),
+                Ok(Row::from(vec!["c", "1"])),
+                Ok(Row::from(vec!["a", "2"])),
+                Err(Error::InconsistentHeaders),
+                Ok(Row::from(vec!["c", "3"])),
+                Ok(Row::from(vec!["b", "4"])),
+                Ok(Row::from(vec!["a", "5"])),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+
+        let re = Group::new(iter, |headers| headers, |row_stream| row_stream, &["name"])
+            .unwrap()
+            .with_order(GroupOrder::Sorted);
+
+        let mut r = re.into_iter();
+
+        assert_eq!(r.next().unwrap().unwrap(), Row::from(vec!["a", "2"]));
+        assert_eq!(r.next().unwrap().unwrap(), Row::from(vec!["a", "5"]));
+        assert_eq!(r.next().unwrap().unwrap(), Row::from(vec!["b", "4"]));
+        assert_eq!(r.next().unwrap().unwrap(), Row::from(vec!["c", "1"]));
+        assert_eq!(r.next().unwrap().unwrap(), Row::from(vec!["c", "3"]));
+
+        match r.next() {
+            Some(Err(Error::InconsistentHeaders)) => {},
+            _ => unreachable!(),
+        }
+
+        assert!(r.next().is_none());
+    }
+
+    #[test]
+    fn test_group_first_seen_order_with_spill() {
+        let iter = MockStream::from_rows(
+            vec![
+                Ok(Row::from(vec!["name", "value"])),
+                Ok(Row::from(vec!["c", "1"])),
+                Ok(Row::from(vec!["a", "2"])),
+                Err(Error::InconsistentHeaders),
+                Ok(Row::from(vec!["c", "3"])),
+                Ok(Row::from(vec!["b", "4"])),
+                Ok(Row::from(vec!["a", "5"])),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+
+        let re = Group::new(iter, |headers| headers, |row_stream| row_stream, &["name"])
+            .unwrap()
+            .with_order(GroupOrder::FirstSeen)
+            .with_memory_budget(2);
+
+        let mut r = re.into_iter();
+
+        assert_eq!(r.next().unwrap().unwrap(), Row::from(vec!["c", "1"]));
+        assert_eq!(r.next().unwrap().unwrap(), Row::from(vec!["c", "3"]));
+        assert_eq!(r.next().unwrap().unwrap(), Row::from(vec!["a", "2"]));
+        assert_eq!(r.next().unwrap().unwrap(), Row::from(vec!["a", "5"]));
+
+        match r.next() {
+            Some(Err(Error::InconsistentHeaders)) => {},
+            _ => unreachable!(),
+        }
+
+        assert_eq!(r.next().unwrap().unwrap(), Row::from(vec!["b", "4"]));
+        assert!(r.next().is_none());
+    }
+
+    #[test]
+    fn test_group_sorted_order_with_spill() {
+        let mut rows = vec![Ok(Row::from(vec!["name", "value"]))];
+
+        for i in 0..60 {
+            rows.push(Ok(Row::from(vec![format!("{:02}", (i * 37) % 50), i.to_string()])));
+        }
+
+        rows.insert(30, Err(Error::InconsistentHeaders));
+
+        let iter = MockStream::from_rows(rows.into_iter()).unwrap();
+        let re = Group::new(iter, |headers| headers, |row_stream| row_stream, &["name"])
+            .unwrap()
+            .with_order(GroupOrder::Sorted)
+            .with_memory_budget(50);
+
+        let mut results: Vec<_> = re.into_iter().collect();
+
+        match results.pop() {
+            Some(Err(Error::InconsistentHeaders)) => {},
+            x => unreachable!("{:?}", x),
+        }
+
+        let results: Vec<(String, usize)> = results
+            .into_iter()
+            .map(|row| {
+                let row = row.unwrap();
+
+                (row[0].to_string(), row[1].parse().unwrap())
+            })
+            .collect();
+        let mut expected = results.clone();
+
+        // stable, so rows of the same group must keep the input order
+        expected.sort_by(|a, b| a.0.cmp(&b.0));
+
+        assert_eq!(results.len(), 60);
+        assert_eq!(results, expected);
+        assert!(results.windows(2).all(|w| w[0].0 != w[1].0 || w[0].1 < w[1].1));
+    }
+
+    #[test]
+    fn test_group_spill_to_disk() {
+        let iter = MockStream::from_rows(
+            vec![
+                Ok(Row::from(vec!["name", "value"])),
+                Ok(Row::from(vec!["a", "1"])),
+                Ok(Row::from(vec!["b", "1"])),
+                Err(Error::InconsistentHeaders),
+                Ok(Row::from(vec!["a", "2"])),
+                Ok(Row::from(vec!["c", "5"])),
+                Ok(Row::from(vec!["b", "1"])),
+                Ok(Row::from(vec!["a", "3"])),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+
+        let re = Group::new(iter, |mut headers| {
+            headers.add("sum").unwrap();
+
+            headers
+        }, |row_stream| {
+            let headers = row_stream.headers().clone();
+            let rows: Vec<_> = row_stream.into_iter().collect();
+            let sum: f64 = rows.iter().filter_map(|row| row.as_ref().ok()).map(|row| {
+                headers.get_field(row, "value").unwrap().parse::<f64>().unwrap()
+            }).sum();
+
+            MockStream::new(rows.into_iter(), headers)
+                .add(ColSpec::Mix {
+                    colname: "sum".to_string(),
+                    coldef: sum.to_string(),
+                })
+                .unwrap()
+        }, &["name"]).unwrap().with_memory_budget(4);
+
+        let mut errors = 0;
+        let mut results: Vec<Row> = re.into_iter().filter_map(|item| match item {
+            Ok(row) => Some(row),
+            Err(Error::InconsistentHeaders) => {
+                errors += 1;
+
+                None
+            },
+            Err(e) => panic!("unexpected error {:?}", e),
+        }).collect();
+
+        results.sort_by(|a, b| a.as_slice().cmp(b.as_slice()));
+
+        assert_eq!(errors, 1);
+        assert_eq!(
+            results,
+            vec![
+                Row::from(vec!["a", "1", "6"]),
+                Row::from(vec!["a", "2", "6"]),
+                Row::from(vec!["a", "3", "6"]),
+                Row::from(vec!["b", "1", "2"]),
+                Row::from(vec!["b", "1", "2"]),
+                Row::from(vec!["c", "5", "5"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_group_with_key() {
+        let iter = MockStream::from_rows(
+            vec![
+                Ok(Row::from(vec!["name", "value"])),
+                Ok(Row::from(vec!["a", "1"])),
+                Err(Error::InconsistentHeaders),
+                Ok(Row::from(vec!["b", "2"])),
+                Ok(Row::from(vec!["a", "3"])),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+
+        let re = Group::new_with_key(iter, |mut headers| {
+            headers.add("key").unwrap();
+
+            headers
+        }, |key, row_stream| {
+            let coldef = match key {
+                GroupKey::Rows(values) => values.join("-"),
+                GroupKey::Errors => "errors".to_string(),
+            };
+
+            row_stream
+                .add(ColSpec::Mix {
+                    colname: "key".to_string(),
+                    coldef,
+                })
+                .unwrap()
+        }, &["name", "value"]).unwrap().with_order(GroupOrder::FirstSeen);
+
+        let mut r = re.into_iter();
+
+        assert_eq!(r.next().unwrap().unwrap(), Row::from(vec!["a", "1", "a-1"]));
+
+        match r.next() {
+            Some(Err(Error::InconsistentHeaders)) => {},
+            _ => unreachable!(),
+        }
+
+        assert_eq!(r.next().unwrap().unwrap(), Row::from(vec!["b", "2", "b-2"]));
+        assert_eq!(r.next().unwrap().unwrap(), Row::from(vec!["a", "3", "a-3"]));
+        assert!(r.next().is_none());
+    }
+
     #[test]
     fn test_nonmatching_headers() {
         let iter = MockStream::from_rows(
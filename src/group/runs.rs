@@ -0,0 +1,237 @@
+//! On-disk storage for the rows of a group that exceeded its memory budget
+//! while its groups must be emitted in order. Rows are written as runs sorted
+//! by the order of their group and merged back, so the groups come out in
+//! order.
+use std::collections::{HashMap, VecDeque};
+use std::hash::BuildHasher;
+
+use super::{GroupKey, GroupOrder, Groups};
+use crate::{
+    Headers, Row, RowResult,
+    sort::{
+        key::{sort_values, SortKey, SortKind, SortValue},
+        runs::{Merge, Runs},
+    },
+};
+
+/// Sorted runs of rows, each one preceded by the fields that give the order
+/// of its group: the key itself for `GroupOrder::Sorted`, the position in
+/// which the key was first seen for `GroupOrder::FirstSeen`.
+pub struct OrderedSpill {
+    runs: Runs,
+    order: GroupOrder,
+    keys: Vec<SortKey>,
+    /// Names for the fields that precede each row, used to sort them
+    headers: Headers,
+    /// Position in which every key was first seen. Unlike the rows it is
+    /// kept in memory for the whole stream.
+    first_seen: HashMap<GroupKey, usize>,
+}
+
+impl OrderedSpill {
+    pub fn new(order: GroupOrder, group_by: &[String]) -> csv::Result<OrderedSpill> {
+        let width = match order {
+            GroupOrder::Sorted => group_by.len(),
+            _ => 1,
+        };
+        let names: Vec<String> = (0..width).map(|i| i.to_string()).collect();
+        let keys = names
+            .iter()
+            .map(|name| match order {
+                GroupOrder::Sorted => SortKey::asc(name, SortKind::Str),
+                _ => SortKey::asc(name, SortKind::Num),
+            })
+            .collect();
+
+        Ok(OrderedSpill {
+            runs: Runs::new()?,
+            order,
+            keys,
+            headers: Headers::from_row(Row::from(names)),
+            first_seen: HashMap::new(),
+        })
+    }
+
+    /// Gives their final position to the keys first seen since the last
+    /// time, keeping their relative order.
+    fn register<S>(&mut self, groups: &Groups<S>) {
+        let mut new: Vec<_> = groups
+            .iter()
+            .filter(|(key, _)| !self.first_seen.contains_key(key))
+            .map(|(key, (index, _))| (*index, key.clone()))
+            .collect();
+
+        new.sort();
+
+        for (_, key) in new {
+            let position = self.first_seen.len();
+
+            self.first_seen.insert(key, position);
+        }
+    }
+
+    /// The fields that precede the rows of the given group
+    fn prefix(&self, key: &GroupKey) -> Row {
+        match (self.order, key) {
+            (GroupOrder::Sorted, GroupKey::Rows(values)) => Row::from(values.clone()),
+            _ => Row::from(vec![self.first_seen[key].to_string()]),
+        }
+    }
+
+    /// The groups of rows, without the errors, along with their prefix and
+    /// the values used to sort them, in order.
+    fn sorted<'g, S>(&mut self, groups: &'g Groups<S>) -> Vec<(Vec<SortValue>, Row, &'g Vec<RowResult>)> {
+        self.register(groups);
+
+        let mut sorted: Vec<_> = groups
+            .iter()
+            .filter(|(key, _)| **key != GroupKey::Errors)
+            .map(|(key, (_, items))| {
+                let prefix = self.prefix(key);
+
+                (sort_values(&self.keys, &self.headers, &prefix), prefix, items)
+            })
+            .collect();
+
+        sorted.sort_by(|a, b| a.0.cmp(&b.0));
+
+        sorted
+    }
+
+    /// Writes the groups of rows as a new run, leaving only the errors
+    /// behind. If writing fails the groups are left untouched.
+    pub fn write<S>(&mut self, groups: &mut Groups<S>) -> csv::Result<()> {
+        let sorted = self.sorted(groups);
+        let rows = sorted.iter().flat_map(|(_, prefix, items)| {
+            // groups of rows only contain Ok items
+            items.iter().flatten().map(move |row| {
+                let mut prefixed = prefix.clone();
+
+                prefixed.extend(row.iter());
+
+                prefixed
+            })
+        });
+
+        self.runs.write(rows)?;
+
+        groups.retain(|key, _| *key == GroupKey::Errors);
+
+        Ok(())
+    }
+
+    /// Merges the runs with the groups still in memory, using the headers and
+    /// grouping columns to recompute the keys of the rows read back.
+    pub fn merge<S: BuildHasher>(mut self, mut groups: Groups<S>, headers: Headers, group_by: Vec<String>) -> csv::Result<MergedGroups> {
+        self.register(&groups);
+
+        let errors = groups.remove(&GroupKey::Errors).map(|(_, items)| {
+            let position = match self.order {
+                GroupOrder::FirstSeen => Some(self.first_seen[&GroupKey::Errors]),
+                _ => None,
+            };
+
+            (position, items)
+        });
+        let mut memory = Vec::new();
+
+        for (key, (_, items)) in groups.drain() {
+            let prefix = self.prefix(&key);
+
+            for row in items.into_iter().flatten() {
+                let mut prefixed = prefix.clone();
+
+                prefixed.extend(row.iter());
+                memory.push((sort_values(&self.keys, &self.headers, &prefixed), prefixed));
+            }
+        }
+
+        // stable, so the rows of each group keep their order
+        memory.sort_by(|a, b| a.0.cmp(&b.0));
+
+        Ok(MergedGroups {
+            width: self.headers.len(),
+            merge: self.runs.merge(memory, self.keys, self.headers)?,
+            next: None,
+            read_errors: VecDeque::new(),
+            errors,
+            headers,
+            group_by,
+        })
+    }
+}
+
+/// Yields the spilled groups in order, reading one group at a time.
+pub struct MergedGroups {
+    merge: Merge,
+    /// Number of fields preceding each row
+    width: usize,
+    /// The first row of the next group, already read
+    next: Option<Row>,
+    /// Rows of the runs that couldn't be read, reported after the group
+    /// they were found in
+    read_errors: VecDeque<csv::Error>,
+    /// The errors found in the stream, to be emitted before the first group
+    /// seen after them, or at the end if there is no such position.
+    errors: Option<(Option<usize>, Vec<RowResult>)>,
+    headers: Headers,
+    group_by: Vec<String>,
+}
+
+impl MergedGroups {
+    /// Removes the fields that precede a row
+    fn strip(&self, row: &Row) -> Row {
+        row.iter().skip(self.width).collect()
+    }
+}
+
+impl Iterator for MergedGroups {
+    type Item = csv::Result<(GroupKey, Vec<RowResult>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.read_errors.pop_front() {
+            return Some(Err(e));
+        }
+
+        let first = match self.next.take() {
+            Some(row) => row,
+            None => match self.merge.next() {
+                Some(Ok(row)) => row,
+                Some(Err(e)) => return Some(Err(e)),
+                None => return self.errors.take().map(|(_, items)| Ok((GroupKey::Errors, items))),
+            },
+        };
+
+        if let Some((Some(position), _)) = self.errors {
+            let first_position: usize = first.get(0).and_then(|p| p.parse().ok()).unwrap_or(0);
+
+            if first_position > position {
+                self.next = Some(first);
+
+                return self.errors.take().map(|(_, items)| Ok((GroupKey::Errors, items)));
+            }
+        }
+
+        let mut items = vec![Ok(self.strip(&first))];
+
+        loop {
+            match self.merge.next() {
+                Some(Ok(row)) => if row.iter().take(self.width).eq(first.iter().take(self.width)) {
+                    items.push(Ok(self.strip(&row)));
+                } else {
+                    self.next = Some(row);
+
+                    break;
+                },
+                // the rest of the group may still come
+                Some(Err(e)) => self.read_errors.push_back(e),
+                None => break,
+            }
+        }
+
+        // rows were keyed before being spilled so the key is there
+        let key = self.headers.key(&self.strip(&first), &self.group_by).unwrap_or_default();
+
+        Some(Ok((GroupKey::Rows(key), items)))
+    }
+}
@@ -0,0 +1,226 @@
+//! On-disk storage for the rows of a group that exceeded its memory budget
+use csv::{ReaderBuilder, Writer, WriterBuilder};
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+
+use crate::{Headers, Row, temp::TempDir};
+
+/// Number of files the rows are partitioned into at each level. Partitions
+/// that are still bigger than the memory budget are partitioned again.
+const PARTITIONS: usize = 16;
+
+/// Writes rows to a set of temporary CSV files, making sure that all the rows
+/// with the same key end up in the same file.
+pub struct Spill {
+    dir: TempDir,
+    writers: Vec<Writer<File>>,
+    sizes: Vec<usize>,
+    depth: usize,
+}
+
+impl Spill {
+    pub fn new() -> csv::Result<Spill> {
+        Spill::with_depth(0)
+    }
+
+    /// The depth is mixed into the hash of the keys so that re-partitioning
+    /// a partition actually splits its keys apart.
+    fn with_depth(depth: usize) -> csv::Result<Spill> {
+        let dir = TempDir::new("csvsc-group")?;
+        let mut writers = Vec::with_capacity(PARTITIONS);
+
+        for i in 0..PARTITIONS {
+            writers.push(
+                WriterBuilder::new()
+                    .flexible(true)
+                    .from_path(dir.path().join(format!("{}.csv", i)))?
+            );
+        }
+
+        Ok(Spill {
+            dir,
+            writers,
+            sizes: vec![0; PARTITIONS],
+            depth,
+        })
+    }
+
+    pub fn write(&mut self, key: &[String], row: &Row) -> csv::Result<()> {
+        let mut hasher = DefaultHasher::new();
+
+        self.depth.hash(&mut hasher);
+        key.hash(&mut hasher);
+
+        let index = (hasher.finish() % self.writers.len() as u64) as usize;
+
+        self.sizes[index] += row.as_slice().len();
+        self.writers[index].write_record(row)
+    }
+
+    /// Finishes writing and returns the partitions so they can be read back.
+    /// Partitions holding more than `budget` bytes of rows are partitioned
+    /// again as they are reached, using the headers and grouping columns to
+    /// recompute the keys.
+    pub fn into_partitions(self, budget: usize, headers: Headers, group_by: Vec<String>) -> csv::Result<Partitions> {
+        let mut partitions = Partitions {
+            dirs: Vec::new(),
+            pending: Vec::new(),
+            budget,
+            headers,
+            group_by,
+        };
+
+        partitions.push(self)?;
+
+        Ok(partitions)
+    }
+}
+
+struct Partition {
+    path: PathBuf,
+    size: usize,
+    depth: usize,
+}
+
+/// The spilled partitions, read back one at a time.
+pub struct Partitions {
+    dirs: Vec<TempDir>,
+    pending: Vec<Partition>,
+    budget: usize,
+    headers: Headers,
+    group_by: Vec<String>,
+}
+
+impl Partitions {
+    /// Flushes the spill and queues its non-empty partitions so that they
+    /// are read before any other pending partition.
+    fn push(&mut self, spill: Spill) -> csv::Result<()> {
+        let Spill { dir, writers, sizes, depth } = spill;
+
+        for (i, (mut writer, size)) in writers.into_iter().zip(sizes).enumerate().rev() {
+            writer.flush()?;
+
+            let path = dir.path().join(format!("{}.csv", i));
+
+            if size == 0 {
+                let _ = fs::remove_file(&path);
+            } else {
+                self.pending.push(Partition { path, size, depth });
+            }
+        }
+
+        self.dirs.push(dir);
+
+        Ok(())
+    }
+
+    /// Splits a partition that doesn't fit in the budget into a new spill.
+    /// Returns `false` if all of its rows landed in the same place, meaning
+    /// they share their key and can't be split any further.
+    fn split(&mut self, partition: &Partition) -> csv::Result<bool> {
+        let mut spill = Spill::with_depth(partition.depth + 1)?;
+        let reader = ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_path(&partition.path)?;
+
+        for row in reader.into_records() {
+            let row = row?;
+            // rows were keyed before being spilled so the key is there
+            let key = self.headers.key(&row, &self.group_by).unwrap_or_default();
+
+            spill.write(&key, &row)?;
+        }
+
+        if spill.sizes.contains(&partition.size) {
+            return Ok(false);
+        }
+
+        let _ = fs::remove_file(&partition.path);
+
+        self.push(spill)?;
+
+        Ok(true)
+    }
+}
+
+impl Iterator for Partitions {
+    type Item = csv::Result<Vec<Row>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let partition = self.pending.pop()?;
+
+        if partition.size > self.budget {
+            match self.split(&partition) {
+                Ok(true) => return self.next(),
+                Ok(false) => {},
+                Err(e) => return Some(Err(e)),
+            }
+        }
+
+        let rows = ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_path(&partition.path)
+            .and_then(|reader| reader.into_records().collect());
+
+        let _ = fs::remove_file(&partition.path);
+
+        Some(rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Spill;
+    use crate::{Headers, Row};
+
+    #[test]
+    fn test_partitions_fit_in_budget() {
+        let headers = Headers::from_row(Row::from(vec!["id", "value"]));
+        let mut spill = Spill::new().unwrap();
+
+        for i in 0..1000 {
+            let key = vec![i.to_string()];
+            let row = Row::from(vec![key[0].as_str(), "xxxxxxxx"]);
+
+            spill.write(&key, &row).unwrap();
+        }
+
+        let budget = 200;
+        let partitions = spill.into_partitions(budget, headers, vec!["id".to_string()]).unwrap();
+        let mut total = 0;
+
+        for partition in partitions {
+            let rows = partition.unwrap();
+            let size: usize = rows.iter().map(|row| row.as_slice().len()).sum();
+
+            assert!(size <= budget);
+
+            total += rows.len();
+        }
+
+        assert_eq!(total, 1000);
+    }
+
+    #[test]
+    fn test_single_key_partition_is_not_split_forever() {
+        let headers = Headers::from_row(Row::from(vec!["id", "value"]));
+        let mut spill = Spill::new().unwrap();
+
+        for _ in 0..100 {
+            spill.write(&["a".to_string()], &Row::from(vec!["a", "xxxxxxxx"])).unwrap();
+        }
+
+        let partitions: Vec<_> = spill
+            .into_partitions(10, headers, vec!["id".to_string()])
+            .unwrap()
+            .map(|p| p.unwrap())
+            .collect();
+
+        assert_eq!(partitions.len(), 1);
+        assert_eq!(partitions[0].len(), 100);
+    }
+}
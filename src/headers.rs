@@ -75,6 +75,42 @@ impl Headers {
         Ok(hasher.finish())
     }
 
+    /// Extracts the values of the columns specified by the second argument
+    /// from the row given as first argument, in the same order. Unlike
+    /// [hash](#method.hash) two rows only share a key if their values are
+    /// actually equal.
+    ///
+    /// ```rust
+    /// use csvsc::{Headers, Row};
+    ///
+    /// let headers = Headers::from_row(Row::from(vec!["id", "name", "val"]));
+    /// let row = Row::from(vec!["1", "juan", "40"]);
+    ///
+    /// assert_eq!(
+    ///     headers.key(&row, &["name".to_string(), "id".to_string()]),
+    ///     Ok(vec!["juan".to_string(), "1".to_string()])
+    /// );
+    /// assert_eq!(
+    ///     headers.key(&row, &["foo".to_string()]),
+    ///     Err("foo".to_string())
+    /// );
+    /// ```
+    ///
+    /// If a column is not found its name is returned as String wrapped in the
+    /// Err variant of the return value
+    pub fn key(&self, row: &Row, columns: &[String]) -> Result<Vec<String>, String> {
+        let mut key = Vec::with_capacity(columns.len());
+
+        for col in columns {
+            match self.get_field(row, col) {
+                Some(field) => key.push(field.to_string()),
+                None => return Err(col.to_string()),
+            }
+        }
+
+        Ok(key)
+    }
+
     /// Adds a new header. It'll fail if the header is already present
     ///
     /// ```rust
@@ -109,7 +145,8 @@ impl Headers {
         &self.names
     }
 
-    fn index(&self, field: &str) -> Option<usize> {
+    /// Position of the given header, if present
+    pub fn index(&self, field: &str) -> Option<usize> {
         self.indexes.get(field).map(|u| *u)
     }
 
@@ -1,65 +1,238 @@
 use csv::Reader;
-use csv::{ByteRecord, ByteRecordsIntoIter};
+use csv::{ByteRecord, ByteRecordsIntoIter, Position};
 use encoding::{DecoderTrap, EncodingRef};
 use std::clone::Clone;
 use std::collections::VecDeque;
-use std::fs::File;
-use std::path::{Path, PathBuf};
+use std::io::{self, Read};
+use std::path::Path;
 
 use crate::{
-    Row, SOURCE_FIELD, Headers, RowStream,
+    Row, SOURCE_FIELD, LINE_FIELD, OFFSET_FIELD, Headers, RowStream,
     error::{Error, RowResult},
 };
 
-fn decode(data: ByteRecord, encoding: EncodingRef) -> Row {
+mod builder;
+mod compression;
+mod json;
+
+pub use builder::{ReaderSourceBuilder, SourceHeaders};
+pub use json::{JsonFields, JsonLinesStream, JsonSource};
+pub(crate) use compression::decompress;
+
+/// What to do with bytes that are not valid in the encoding of a source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DecodingPolicy {
+    /// Replace them with U+FFFD.
+    #[default]
+    Replace,
+
+    /// Yield `Error::InvalidEncoding` instead of the row.
+    Strict,
+
+    /// Drop them.
+    Ignore,
+}
+
+impl DecodingPolicy {
+    fn trap(self) -> DecoderTrap {
+        match self {
+            DecodingPolicy::Replace => DecoderTrap::Replace,
+            DecodingPolicy::Strict => DecoderTrap::Strict,
+            DecodingPolicy::Ignore => DecoderTrap::Ignore,
+        }
+    }
+}
+
+/// Decodes every field of the record. Fails with the index of the first field
+/// that can't be decoded under the given policy.
+fn decode_with(data: &ByteRecord, encoding: EncodingRef, policy: DecodingPolicy) -> Result<Row, usize> {
     let mut row = Row::with_capacity(data.as_slice().len(), data.len());
 
-    for item in data.iter() {
-        row.push_field(&encoding.decode(item, DecoderTrap::Replace).unwrap());
+    for (i, item) in data.iter().enumerate() {
+        row.push_field(&encoding.decode(item, policy.trap()).map_err(|_| i)?);
     }
 
-    row
+    Ok(row)
+}
+
+/// Decodes the headers of a source into the names of its columns
+fn decode_columns(data: &ByteRecord, encoding: EncodingRef, policy: DecodingPolicy) -> Result<Vec<String>, usize> {
+    Ok(decode_with(data, encoding, policy)?.iter().map(|c| c.to_string()).collect())
 }
 
-/// Represents a file as source of CSV data.
+/// Label used as `SOURCE_FIELD` for data read from the standard input
+pub const STDIN_LABEL: &str = "<stdin>";
+
+/// Represents a source of CSV data, like a file or the standard input. Each
+/// source has a label, usually its path, that is used as the value of the
+/// `SOURCE_FIELD` column.
 pub struct ReaderSource {
-    reader: Reader<File>,
-    path: PathBuf,
+    reader: Reader<Box<dyn Read + Send>>,
+    name: String,
+    flexible: bool,
+    /// Column names given instead of reading them from the first line
+    names: Option<ByteRecord>,
+    encoding: Option<EncodingRef>,
+    /// Error found reading the headers ahead of time, reported when the
+    /// source is reached
+    header_error: Option<Error>,
 }
 
 impl ReaderSource {
-    pub fn from_reader<P: AsRef<Path>>(reader: Reader<File>, path: P) -> ReaderSource {
+    pub fn from_reader(reader: Reader<Box<dyn Read + Send>>, name: &str) -> ReaderSource {
         ReaderSource {
             reader,
-            path: path.as_ref().to_path_buf(),
+            name: name.to_string(),
+            flexible: false,
+            names: None,
+            encoding: None,
+            header_error: None,
         }
     }
 
+    /// Reads CSV data from anything implementing `io::Read`, identified by
+    /// the given label.
+    ///
+    /// ```rust
+    /// use std::io::Cursor;
+    /// use csvsc::ReaderSource;
+    ///
+    /// let source = ReaderSource::from_read(Cursor::new("a,b\n1,2\n"), "memory");
+    /// ```
+    pub fn from_read<R: Read + Send + 'static>(read: R, name: &str) -> ReaderSource {
+        ReaderSource::from_reader(
+            csv::Reader::from_reader(Box::new(read)),
+            name,
+        )
+    }
+
+    /// Reads CSV data from the standard input, labeled as `<stdin>`.
+    pub fn from_stdin() -> ReaderSource {
+        ReaderSource::from_read(io::stdin(), STDIN_LABEL)
+    }
+
+    /// Starts building a source with a custom CSV dialect
+    pub fn builder() -> ReaderSourceBuilder {
+        ReaderSourceBuilder::new()
+    }
+
     pub fn from_path<P: AsRef<Path>>(path: P) -> Result<ReaderSource, csv::Error> {
-        Ok(ReaderSource::from_reader(
-            csv::Reader::from_path(&path)?,
-            path,
-        ))
+        ReaderSourceBuilder::new().from_path(path)
+    }
+
+    /// The encoding set or detected for this source, if any. Sources without
+    /// one are decoded with the encoding of the stream.
+    ///
+    /// ```rust
+    /// use csvsc::ReaderSource;
+    ///
+    /// let source = ReaderSource::builder()
+    ///     .detect_encoding(true)
+    ///     .from_path("test/assets/windows1252/data.csv")
+    ///     .unwrap();
+    ///
+    /// assert_eq!(source.encoding().unwrap().name(), "windows-1252");
+    /// ```
+    pub fn encoding(&self) -> Option<EncodingRef> {
+        self.encoding
+    }
+
+    fn byte_headers(&mut self) -> csv::Result<ByteRecord> {
+        match self.names {
+            Some(ref names) => Ok(names.clone()),
+            None => Ok(self.reader.byte_headers()?.clone()),
+        }
     }
 
-    fn headers(&mut self) -> ByteRecord {
-        let mut data = self.reader.byte_headers().unwrap().clone();
-        data.push_field(SOURCE_FIELD.as_bytes());
+    /// The headers of this source, along with the names of its columns
+    /// without `SOURCE_FIELD`, decoded under the given policy
+    fn column_names(&mut self, encoding: EncodingRef, policy: DecodingPolicy) -> Result<(ByteRecord, Vec<String>), Error> {
+        if let Some(e) = self.header_error.take() {
+            return Err(e);
+        }
+
+        let headers = match self.byte_headers() {
+            Ok(headers) => headers,
+            Err(e) => return Err(read_error(&self.name, None, e)),
+        };
 
-        data
+        match decode_columns(&headers, encoding, policy) {
+            Ok(columns) => Ok((headers, columns)),
+            Err(field) => Err(Error::InvalidEncoding(self.name.clone(), Position::new(), field)),
+        }
     }
 }
 
+/// Attaches the label of a source and a position to an error of its reader.
+/// The position of the error is preferred over the given one. Rows of the
+/// wrong length are reported as `Error::InconsistentSizeOfRows`.
+fn read_error(name: &str, position: Option<&Position>, error: csv::Error) -> Error {
+    let position = error.position().or(position).cloned().unwrap_or_else(Position::new);
+
+    match error.kind() {
+        csv::ErrorKind::UnequalLengths { .. } => Error::InconsistentSizeOfRows(name.to_string(), position),
+        _ => Error::Read(name.to_string(), position, error),
+    }
+}
+
+/// How an [InputStream](struct.InputStream.html) deals with sources whose
+/// headers differ.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum HeaderAlignment {
+    /// Every source must have the same headers, in the same order. A source
+    /// with different headers yields `Error::InconsistentSourceHeaders`.
+    #[default]
+    Strict,
+
+    /// The columns are the union of the headers of every source, in the
+    /// order they are first seen.
+    Union,
+
+    /// Exactly the given columns. Columns of the sources not listed here are
+    /// dropped.
+    Schema(Vec<String>),
+}
+
+/// For each column of the stream the index of the same column in a source
+fn column_mapping<'a, I>(stream_columns: I, columns: &[String]) -> Vec<Option<usize>>
+where
+    I: Iterator<Item = &'a str>,
+{
+    stream_columns
+        .map(|h| columns.iter().position(|c| c == h))
+        .collect()
+}
+
 /// A structure for creating a transformation chain from input files.
 pub struct InputStream {
     readers: VecDeque<ReaderSource>,
-    current_records: ByteRecordsIntoIter<File>,
-    current_path: PathBuf,
+    /// `None` if the headers of the current source couldn't be read
+    current_records: Option<ByteRecordsIntoIter<Box<dyn Read + Send>>>,
+    current_name: String,
+    current_flexible: bool,
+    /// Headers of the current source as read, kept to decode them again if
+    /// the decoding policy changes
+    current_header_bytes: ByteRecord,
+    current_columns: Vec<String>,
+    current_position: Option<Position>,
+    /// Error reading the headers of the first source, reported first
+    header_error: Option<Error>,
+    /// Where to find each column in the rows of the current source, and the
+    /// value of missing columns, when aligning headers
+    alignment: Option<(Vec<Option<usize>>, String)>,
+    header_alignment: HeaderAlignment,
+    current_encoding: EncodingRef,
+    decoding: DecodingPolicy,
+    /// Used for sources without an encoding of their own
     encoding: EncodingRef,
+    line_column: bool,
+    offset_column: bool,
     headers: Headers,
 }
 
 impl InputStream {
+    /// Reads the given sources one after the other. Sources without an
+    /// encoding of their own are decoded with `encoding`.
     pub fn from_readers<I>(readers: I, encoding: EncodingRef) -> InputStream
     where
         I: IntoIterator<Item = ReaderSource>,
@@ -78,13 +251,209 @@ impl InputStream {
     }
 
     fn new(mut reader_source: ReaderSource, encoding: EncodingRef) -> InputStream {
-        InputStream {
+        let current_encoding = reader_source.encoding.unwrap_or(encoding);
+        let (current_header_bytes, current_columns, current_records) = match reader_source.column_names(current_encoding, DecodingPolicy::Replace) {
+            Ok((bytes, columns)) => (bytes, columns, Some(reader_source.reader.into_byte_records())),
+            Err(e) => {
+                // reported by the first call to next()
+                reader_source.header_error = Some(e);
+
+                (ByteRecord::new(), Vec::new(), None)
+            },
+        };
+
+        let mut input_stream = InputStream {
             readers: VecDeque::new(),
-            headers: Headers::from_row(decode(reader_source.headers(), encoding)),
-            current_records: reader_source.reader.into_byte_records(),
-            current_path: reader_source.path,
+            headers: Headers::from_row(Row::new()),
+            current_header_bytes,
+            current_columns,
+            current_position: None,
+            current_records,
+            header_error: reader_source.header_error,
+            current_name: reader_source.name,
+            current_flexible: reader_source.flexible,
+            alignment: None,
+            header_alignment: HeaderAlignment::Strict,
+            current_encoding,
+            decoding: DecodingPolicy::Replace,
             encoding,
+            line_column: false,
+            offset_column: false,
+        };
+
+        input_stream.headers = input_stream.build_headers(&input_stream.current_columns);
+
+        input_stream
+    }
+
+    /// Headers made of the given columns followed by the virtual ones
+    fn build_headers(&self, columns: &[String]) -> Headers {
+        let mut header_row = Row::with_capacity(0, columns.len() + 3);
+
+        for column in columns.iter() {
+            header_row.push_field(column);
+        }
+
+        header_row.push_field(SOURCE_FIELD);
+
+        if self.line_column {
+            header_row.push_field(LINE_FIELD);
         }
+
+        if self.offset_column {
+            header_row.push_field(OFFSET_FIELD);
+        }
+
+        Headers::from_row(header_row)
+    }
+
+    /// Number of columns added by the stream after the ones of the sources
+    fn virtual_columns(&self) -> usize {
+        1 + self.line_column as usize + self.offset_column as usize
+    }
+
+    /// The columns of the stream that come from the sources
+    fn stream_columns(&self) -> impl Iterator<Item = &str> {
+        self.headers.iter().take(self.headers.len() - self.virtual_columns())
+    }
+
+    /// Adds the `LINE_FIELD` column, after `SOURCE_FIELD`, with the line
+    /// where each record starts in its source.
+    ///
+    /// ```rust
+    /// use csvsc::{InputStream, ReaderSource, Row, SOURCE_FIELD, LINE_FIELD};
+    /// use encoding::all::UTF_8;
+    ///
+    /// let mut stream = InputStream::from_readers(
+    ///     vec![ReaderSource::from_path("test/assets/1.csv").unwrap()],
+    ///     UTF_8,
+    /// ).with_line_column();
+    ///
+    /// assert_eq!(*stream.headers(), Row::from(vec!["a", "b", SOURCE_FIELD, LINE_FIELD]));
+    /// assert_eq!(stream.next().unwrap().unwrap(), Row::from(vec!["1", "3", "test/assets/1.csv", "2"]));
+    /// ```
+    pub fn with_line_column(mut self) -> InputStream {
+        let columns: Vec<String> = self.stream_columns().map(|c| c.to_string()).collect();
+
+        self.line_column = true;
+        self.headers = self.build_headers(&columns);
+
+        self
+    }
+
+    /// Adds the `OFFSET_FIELD` column, after `SOURCE_FIELD` and
+    /// `LINE_FIELD`, with the byte offset where each record starts in its
+    /// source. Offsets are counted like those of [position](#method.position).
+    ///
+    /// ```rust
+    /// use csvsc::{InputStream, ReaderSource, Row, SOURCE_FIELD, LINE_FIELD, OFFSET_FIELD};
+    /// use encoding::all::UTF_8;
+    ///
+    /// let mut stream = InputStream::from_readers(
+    ///     vec![ReaderSource::from_path("test/assets/1.csv").unwrap()],
+    ///     UTF_8,
+    /// ).with_line_column().with_offset_column();
+    ///
+    /// assert_eq!(*stream.headers(), Row::from(vec!["a", "b", SOURCE_FIELD, LINE_FIELD, OFFSET_FIELD]));
+    /// assert_eq!(stream.next().unwrap().unwrap(), Row::from(vec!["1", "3", "test/assets/1.csv", "2", "4"]));
+    /// ```
+    pub fn with_offset_column(mut self) -> InputStream {
+        let columns: Vec<String> = self.stream_columns().map(|c| c.to_string()).collect();
+
+        self.offset_column = true;
+        self.headers = self.build_headers(&columns);
+
+        self
+    }
+
+    /// The position in its source, including byte offset and line, of the
+    /// last record read.
+    ///
+    /// Byte offsets count the data as the CSV reader sees it, that is after
+    /// decompressing the source and stripping its byte order mark, so they
+    /// don't match offsets in a compressed file or one starting with a BOM.
+    pub fn position(&self) -> Option<&Position> {
+        self.current_position.as_ref()
+    }
+
+    /// Sets how sources with different headers are handled. Unless the
+    /// alignment is strict the fields of every source are reordered to match
+    /// the headers of the stream, and missing columns are filled with `fill`.
+    ///
+    /// ```rust
+    /// use csvsc::{HeaderAlignment, InputStream, ReaderSource, Row, SOURCE_FIELD};
+    /// use encoding::all::UTF_8;
+    ///
+    /// let stream = InputStream::from_readers(
+    ///     vec![
+    ///         ReaderSource::from_path("test/assets/1.csv").unwrap(),
+    ///         ReaderSource::from_path("test/assets/3.csv").unwrap(),
+    ///     ],
+    ///     UTF_8,
+    /// ).with_alignment(HeaderAlignment::Union, "");
+    ///
+    /// assert_eq!(*stream.headers(), Row::from(vec!["a", "b", "c", SOURCE_FIELD]));
+    /// ```
+    pub fn with_alignment(mut self, alignment: HeaderAlignment, fill: &str) -> InputStream {
+        self.header_alignment = alignment.clone();
+
+        let columns = match alignment {
+            HeaderAlignment::Strict => {
+                self.headers = self.build_headers(&self.current_columns);
+                self.alignment = None;
+
+                return self;
+            },
+            HeaderAlignment::Union => {
+                let mut columns = self.current_columns.clone();
+
+                // sources whose headers failed will report it when reached
+                for source in self.readers.iter_mut().filter(|s| s.header_error.is_none()) {
+                    let encoding = source.encoding.unwrap_or(self.encoding);
+
+                    match source.column_names(encoding, self.decoding) {
+                        Ok((_, names)) => for column in names {
+                            if !columns.contains(&column) {
+                                columns.push(column);
+                            }
+                        },
+                        Err(e) => source.header_error = Some(e),
+                    }
+                }
+
+                columns
+            },
+            HeaderAlignment::Schema(columns) => columns,
+        };
+
+        self.headers = self.build_headers(&columns);
+        self.alignment = Some((column_mapping(columns.iter().map(|c| c.as_str()), &self.current_columns), fill.to_string()));
+
+        self
+    }
+
+    /// Sets what to do with bytes that are not valid in the encoding of their
+    /// source, replacing them by default. Headers are decoded under the same
+    /// policy; with a strict one a source whose headers can't be decoded
+    /// yields `Error::InvalidEncoding` and its rows are skipped.
+    pub fn with_decoding(mut self, decoding: DecodingPolicy) -> InputStream {
+        self.decoding = decoding;
+
+        // the headers of the first source were already decoded
+        if self.current_records.is_some() {
+            match decode_columns(&self.current_header_bytes, self.current_encoding, decoding) {
+                Ok(columns) => self.current_columns = columns,
+                Err(field) => {
+                    self.header_error = Some(Error::InvalidEncoding(self.current_name.clone(), Position::new(), field));
+                    self.current_records = None;
+                },
+            }
+        }
+
+        let fill = self.alignment.as_ref().map(|(_, fill)| fill.clone()).unwrap_or_default();
+        let alignment = self.header_alignment.clone();
+
+        self.with_alignment(alignment, &fill)
     }
 
     fn add(&mut self, item: ReaderSource) {
@@ -106,32 +475,101 @@ impl Iterator for InputStream {
     type Item = RowResult;
 
     fn next(&mut self) -> Option<Self::Item> {
-        match self.current_records.next() {
+        if let Some(e) = self.header_error.take() {
+            return Some(Err(e));
+        }
+
+        match self.current_records.as_mut().and_then(|records| records.next()) {
             Some(Ok(reg)) => {
-                let mut str_reg = decode(reg, self.encoding);
-                str_reg.push_field(&self.current_path.to_string_lossy());
+                let position = reg.position().cloned().unwrap_or_else(Position::new);
+                let mut str_reg = match decode_with(&reg, self.current_encoding, self.decoding) {
+                    Ok(row) => row,
+                    Err(field) => return Some(Err(Error::InvalidEncoding(
+                        self.current_name.clone(),
+                        position,
+                        field,
+                    ))),
+                };
+
+                self.current_position = Some(position.clone());
+
+                if let Some((ref mapping, ref fill)) = self.alignment {
+                    if str_reg.len() > self.current_columns.len() {
+                        return Some(Err(Error::InconsistentSizeOfRows(
+                            self.current_name.clone(),
+                            position,
+                        )));
+                    }
+
+                    let mut aligned = Row::with_capacity(str_reg.as_slice().len(), self.headers.len());
+
+                    for index in mapping.iter() {
+                        aligned.push_field(index.and_then(|i| str_reg.get(i)).unwrap_or(fill));
+                    }
+
+                    str_reg = aligned;
+                } else if self.current_flexible {
+                    while str_reg.len() + self.virtual_columns() < self.headers.len() {
+                        str_reg.push_field("");
+                    }
+                }
+
+                str_reg.push_field(&self.current_name);
+
+                if self.line_column {
+                    str_reg.push_field(&position.line().to_string());
+                }
+
+                if self.offset_column {
+                    str_reg.push_field(&position.byte().to_string());
+                }
 
                 if str_reg.len() != self.headers.len() {
                     return Some(Err(Error::InconsistentSizeOfRows(
-                        self.current_path.clone(),
+                        self.current_name.clone(),
+                        position,
                     )));
                 }
 
                 Some(Ok(str_reg))
             }
 
-            Some(Err(e)) => Some(Err(Error::Csv(e))),
+            Some(Err(e)) => {
+                let position = self.current_records.as_ref().map(|records| records.reader().position());
+
+                Some(Err(read_error(&self.current_name, position, e)))
+            },
 
             None => match self.readers.pop_front() {
                 Some(mut rs) => {
-                    let new_headers = decode(rs.headers(), self.encoding);
+                    let encoding = rs.encoding.unwrap_or(self.encoding);
+                    let (bytes, columns) = match rs.column_names(encoding, self.decoding) {
+                        Ok(headers) => headers,
+                        Err(e) => {
+                            // skip the rows of this source
+                            self.current_records = None;
 
-                    if new_headers != self.headers {
-                        return Some(Err(Error::InconsistentHeaders));
+                            return Some(Err(e));
+                        },
+                    };
+
+                    if self.alignment.is_some() {
+                        let new_mapping = column_mapping(self.stream_columns(), &columns);
+
+                        if let Some((ref mut mapping, _)) = self.alignment {
+                            *mapping = new_mapping;
+                        }
+                    } else if !self.stream_columns().eq(columns.iter().map(|c| c.as_str())) {
+                        return Some(Err(Error::InconsistentSourceHeaders(rs.name, Position::new())));
                     }
 
-                    self.current_records = rs.reader.into_byte_records();
-                    self.current_path = rs.path;
+                    self.current_header_bytes = bytes;
+                    self.current_columns = columns;
+                    self.current_records = Some(rs.reader.into_byte_records());
+                    self.current_name = rs.name;
+                    self.current_flexible = rs.flexible;
+                    self.current_encoding = encoding;
+                    self.current_position = None;
 
                     self.next()
                 }
@@ -144,9 +582,12 @@ impl Iterator for InputStream {
 
 #[cfg(test)]
 mod tests {
-    use super::{InputStream, ReaderSource, Row, SOURCE_FIELD};
+    use super::{
+        DecodingPolicy, HeaderAlignment, InputStream, ReaderSource, Row, SourceHeaders, LINE_FIELD, OFFSET_FIELD, SOURCE_FIELD,
+    };
     use crate::error::Error;
     use encoding::all::{UTF_8, WINDOWS_1252};
+    use std::io::{self, Cursor, Read};
 
     #[test]
     fn test_read_concatenated() {
@@ -202,6 +643,493 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_encoding_per_source() {
+        let sources = vec![
+            ReaderSource::from_path("test/assets/windows1252/data.csv").unwrap(),
+            ReaderSource::builder()
+                .encoding(UTF_8)
+                .from_read(Cursor::new("name\nñandú\n"), "memory")
+                .unwrap(),
+        ];
+        let input_stream = InputStream::from_readers(sources, WINDOWS_1252);
+
+        assert_eq!(
+            input_stream.map(|r| r.unwrap()).collect::<Vec<_>>(),
+            vec![
+                Row::from(vec!["árbol", "test/assets/windows1252/data.csv"]),
+                Row::from(vec!["ñandú", "memory"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_detect_encoding() {
+        let sources: Vec<_> = ["test/assets/windows1252/data.csv", "test/assets/bom.csv", "test/assets/1.csv"]
+            .iter()
+            .map(|f| ReaderSource::builder().detect_encoding(true).from_path(f).unwrap())
+            .collect();
+
+        assert_eq!(
+            sources.iter().map(|s| s.encoding().unwrap().name()).collect::<Vec<_>>(),
+            vec!["windows-1252", "utf-8", "utf-8"]
+        );
+
+        let mut input_stream = InputStream::from_readers(sources.into_iter().take(1), UTF_8);
+
+        assert_eq!(
+            input_stream.next().unwrap().unwrap(),
+            Row::from(vec!["árbol", "test/assets/windows1252/data.csv"])
+        );
+    }
+
+    #[test]
+    fn test_strip_bom() {
+        let source = ReaderSource::builder().from_path("test/assets/bom.csv").unwrap();
+        let mut input_stream = InputStream::from_readers(vec![source], UTF_8);
+
+        assert_eq!(
+            *input_stream.headers(),
+            Row::from(vec!["a", "b", SOURCE_FIELD])
+        );
+        assert_eq!(
+            input_stream.next().unwrap().unwrap(),
+            Row::from(vec!["7", "8", "test/assets/bom.csv"])
+        );
+    }
+
+    #[test]
+    fn test_decoding_policy() {
+        let read = |policy| {
+            let source = ReaderSource::builder()
+                .encoding(UTF_8)
+                .from_read(Cursor::new(&b"id,name\n1,ok\n2,\xe1rbol\n"[..]), "memory")
+                .unwrap();
+
+            InputStream::from_readers(vec![source], UTF_8)
+                .with_decoding(policy)
+                .collect::<Vec<_>>()
+        };
+
+        let mut strict = read(DecodingPolicy::Strict).into_iter();
+
+        assert_eq!(strict.next().unwrap().unwrap(), Row::from(vec!["1", "ok", "memory"]));
+
+        match strict.next() {
+            Some(Err(Error::InvalidEncoding(name, position, 1))) => {
+                assert_eq!(name, "memory");
+                assert_eq!(position.line(), 3);
+            },
+            _ => unreachable!(),
+        }
+
+        assert_eq!(
+            read(DecodingPolicy::Ignore).pop().unwrap().unwrap(),
+            Row::from(vec!["2", "rbol", "memory"])
+        );
+        assert_eq!(
+            read(DecodingPolicy::Replace).pop().unwrap().unwrap(),
+            Row::from(vec!["2", "\u{fffd}rbol", "memory"])
+        );
+    }
+
+    #[test]
+    fn test_decoding_policy_headers() {
+        let source = |data: &'static [u8], name| ReaderSource::builder()
+            .encoding(UTF_8)
+            .from_read(Cursor::new(data), name)
+            .unwrap();
+
+        let mut stream = InputStream::from_readers(
+            vec![source(b"id,\xe1rbol\n1,2\n", "first"), source(b"id,\xe1rbol\n3,4\n", "second")],
+            UTF_8,
+        ).with_decoding(DecodingPolicy::Strict);
+
+        for expected in ["first", "second"].iter() {
+            match stream.next() {
+                Some(Err(Error::InvalidEncoding(name, position, 1))) => {
+                    assert_eq!(name, *expected);
+                    assert_eq!(position.line(), 1);
+                },
+                x => unreachable!("{:?}", x),
+            }
+        }
+
+        assert!(stream.next().is_none());
+
+        let stream = InputStream::from_readers(vec![source(b"id,\xe1rbol\n1,2\n", "first")], UTF_8)
+            .with_decoding(DecodingPolicy::Ignore);
+
+        assert_eq!(*stream.headers(), Row::from(vec!["id", "rbol", SOURCE_FIELD]));
+    }
+
+    #[test]
+    fn test_line_column() {
+        let sources = vec![
+            ReaderSource::from_read(Cursor::new("a,b\n1,\"two\nlines\"\n3,4\n"), "memory"),
+            ReaderSource::from_path("test/assets/1.csv").unwrap(),
+        ];
+        let mut input_stream = InputStream::from_readers(sources, UTF_8).with_line_column();
+
+        assert_eq!(
+            *input_stream.headers(),
+            Row::from(vec!["a", "b", SOURCE_FIELD, LINE_FIELD])
+        );
+
+        assert_eq!(
+            input_stream.next().unwrap().unwrap(),
+            Row::from(vec!["1", "two\nlines", "memory", "2"])
+        );
+        assert_eq!(
+            input_stream.next().unwrap().unwrap(),
+            Row::from(vec!["3", "4", "memory", "4"])
+        );
+        assert_eq!(input_stream.position().unwrap().byte(), 18);
+        assert_eq!(
+            input_stream.next().unwrap().unwrap(),
+            Row::from(vec!["1", "3", "test/assets/1.csv", "2"])
+        );
+    }
+
+    #[test]
+    fn test_offset_column() {
+        let sources = vec![
+            ReaderSource::from_read(Cursor::new("a,b\n1,\"two\nlines\"\n3,4\n"), "memory"),
+        ];
+        let mut input_stream = InputStream::from_readers(sources, UTF_8)
+            .with_offset_column()
+            .with_alignment(HeaderAlignment::Union, "");
+
+        assert_eq!(
+            *input_stream.headers(),
+            Row::from(vec!["a", "b", SOURCE_FIELD, OFFSET_FIELD])
+        );
+
+        assert_eq!(
+            input_stream.next().unwrap().unwrap(),
+            Row::from(vec!["1", "two\nlines", "memory", "4"])
+        );
+        assert_eq!(
+            input_stream.next().unwrap().unwrap(),
+            Row::from(vec!["3", "4", "memory", "18"])
+        );
+    }
+
+    #[test]
+    fn test_line_column_aligned() {
+        let filenames = ["test/assets/1.csv", "test/assets/3.csv"];
+        let input_stream = InputStream::from_readers(
+            filenames.iter().map(|f| ReaderSource::from_path(f).unwrap()),
+            UTF_8,
+        ).with_line_column().with_alignment(HeaderAlignment::Union, "");
+
+        assert_eq!(
+            *input_stream.headers(),
+            Row::from(vec!["a", "b", "c", SOURCE_FIELD, LINE_FIELD])
+        );
+
+        let rows: Vec<Row> = input_stream.map(|r| r.unwrap()).collect();
+
+        assert_eq!(rows[2].get(4), Some("2"));
+        assert_eq!(rows[2].get(3), Some("test/assets/3.csv"));
+    }
+
+    #[test]
+    fn test_inconsistent_size_position() {
+        let sources = vec![
+            ReaderSource::builder()
+                .flexible(true)
+                .from_read(Cursor::new("a,b\n1,2\n1,2,3\n"), "memory")
+                .unwrap(),
+        ];
+        let mut input_stream = InputStream::from_readers(sources, UTF_8);
+
+        input_stream.next().unwrap().unwrap();
+
+        match input_stream.next() {
+            Some(Err(Error::InconsistentSizeOfRows(name, position))) => {
+                assert_eq!(name, "memory");
+                assert_eq!(position.line(), 3);
+                assert_eq!(position.byte(), 8);
+            },
+            x => unreachable!("{:?}", x),
+        }
+    }
+
+    #[test]
+    fn test_inconsistent_size_not_flexible() {
+        let sources = vec![ReaderSource::from_read(Cursor::new("a,b\n1,2\n1,2,3\n"), "memory")];
+        let mut input_stream = InputStream::from_readers(sources, UTF_8);
+
+        input_stream.next().unwrap().unwrap();
+
+        match input_stream.next() {
+            Some(Err(Error::InconsistentSizeOfRows(name, position))) => {
+                assert_eq!(name, "memory");
+                assert_eq!(position.line(), 3);
+            },
+            x => unreachable!("{:?}", x),
+        }
+    }
+
+    #[test]
+    fn test_read_error_has_label_and_position() {
+        // fails after giving the headers and one row
+        struct Broken(Cursor<&'static str>);
+
+        impl Read for Broken {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                match self.0.read(buf)? {
+                    0 => Err(io::Error::other("broken")),
+                    n => Ok(n),
+                }
+            }
+        }
+
+        let sources = vec![ReaderSource::from_read(Broken(Cursor::new("a,b\n1,2\n")), "broken")];
+        let mut input_stream = InputStream::from_readers(sources, UTF_8);
+
+        input_stream.next().unwrap().unwrap();
+
+        match input_stream.next() {
+            Some(Err(Error::Read(name, position, _))) => {
+                assert_eq!(name, "broken");
+                assert_eq!(position.line(), 3);
+            },
+            x => unreachable!("{:?}", x),
+        }
+    }
+
+    #[test]
+    fn test_read_dialect() {
+        let source = ReaderSource::builder()
+            .delimiter(b';')
+            .quote(b'\'')
+            .comment(Some(b'#'))
+            .trim(true)
+            .from_path("test/assets/semicolon.csv")
+            .unwrap();
+        let mut input_stream = InputStream::from_readers(vec![source], UTF_8);
+
+        assert_eq!(
+            *input_stream.headers(),
+            Row::from(vec!["nombre", "precio", SOURCE_FIELD])
+        );
+
+        assert_eq!(
+            input_stream.next().unwrap().unwrap(),
+            Row::from(vec!["café; con leche", "12,5", "test/assets/semicolon.csv"])
+        );
+        assert_eq!(
+            input_stream.next().unwrap().unwrap(),
+            Row::from(vec!["pan", "3", "test/assets/semicolon.csv"])
+        );
+    }
+
+    #[test]
+    fn test_streams_are_send() {
+        fn assert_send<T: Send>() {}
+
+        assert_send::<InputStream>();
+        assert_send::<crate::JsonLinesStream>();
+        assert_send::<crate::Flush<InputStream>>();
+        assert_send::<<crate::Flush<InputStream> as IntoIterator>::IntoIter>();
+    }
+
+    #[test]
+    fn test_read_from_read() {
+        let sources = vec![
+            ReaderSource::from_read(Cursor::new("a,b\n1,2\n"), "memory"),
+            ReaderSource::from_path("test/assets/2.csv").unwrap(),
+        ];
+        let input_stream = InputStream::from_readers(sources, UTF_8);
+
+        let results: Vec<Row> = input_stream.map(|r| r.unwrap()).collect();
+
+        assert_eq!(
+            results,
+            vec![
+                Row::from(vec!["1", "2", "memory"]),
+                Row::from(vec!["2", "2", "test/assets/2.csv"]),
+                Row::from(vec!["4", "3", "test/assets/2.csv"]),
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn test_read_gzip() {
+        let filenames = ["test/assets/1.csv.gz", "test/assets/1-gzip.data"];
+        let input_stream = InputStream::from_readers(
+            filenames.iter().map(|f| ReaderSource::from_path(f).unwrap()),
+            UTF_8,
+        );
+
+        let results: Vec<Row> = input_stream.map(|r| r.unwrap()).collect();
+
+        assert_eq!(
+            results,
+            vec![
+                Row::from(vec!["1", "3", "test/assets/1.csv.gz"]),
+                Row::from(vec!["5", "2", "test/assets/1.csv.gz"]),
+                Row::from(vec!["1", "3", "test/assets/1-gzip.data"]),
+                Row::from(vec!["5", "2", "test/assets/1-gzip.data"]),
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn test_read_zstd() {
+        let input_stream = InputStream::from_readers(
+            vec![ReaderSource::from_path("test/assets/1.csv.zst").unwrap()],
+            UTF_8,
+        );
+
+        assert_eq!(
+            input_stream.last().unwrap().unwrap(),
+            Row::from(vec!["5", "2", "test/assets/1.csv.zst"])
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "bzip2")]
+    fn test_read_bzip2() {
+        let input_stream = InputStream::from_readers(
+            vec![ReaderSource::from_path("test/assets/1.csv.bz2").unwrap()],
+            UTF_8,
+        );
+
+        assert_eq!(
+            input_stream.last().unwrap().unwrap(),
+            Row::from(vec!["5", "2", "test/assets/1.csv.bz2"])
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "gzip"))]
+    fn test_read_gzip_disabled() {
+        assert!(ReaderSource::from_path("test/assets/1.csv.gz").is_err());
+    }
+
+    #[test]
+    fn test_read_sniffed_delimiter() {
+        let source = ReaderSource::builder()
+            .sniff_delimiter(true)
+            .from_path("test/assets/data.tsv")
+            .unwrap();
+        let mut input_stream = InputStream::from_readers(vec![source], UTF_8);
+
+        assert_eq!(
+            input_stream.next().unwrap().unwrap(),
+            Row::from(vec!["1", "2", "test/assets/data.tsv"])
+        );
+    }
+
+    #[test]
+    fn test_read_headerless() {
+        let source = ReaderSource::builder()
+            .headers(SourceHeaders::Generated)
+            .from_path("test/assets/1.csv")
+            .unwrap();
+        let mut input_stream = InputStream::from_readers(vec![source], UTF_8);
+
+        assert_eq!(
+            *input_stream.headers(),
+            Row::from(vec!["col1", "col2", SOURCE_FIELD])
+        );
+        assert_eq!(
+            input_stream.next().unwrap().unwrap(),
+            Row::from(vec!["a", "b", "test/assets/1.csv"])
+        );
+
+        let source = ReaderSource::builder()
+            .headers(SourceHeaders::Names(vec!["x".to_string(), "y".to_string()]))
+            .from_read(Cursor::new("1,2\n3,4\n"), "memory")
+            .unwrap();
+        let input_stream = InputStream::from_readers(vec![source], UTF_8);
+
+        assert_eq!(
+            *input_stream.headers(),
+            Row::from(vec!["x", "y", SOURCE_FIELD])
+        );
+        assert_eq!(input_stream.count(), 2);
+    }
+
+    #[test]
+    fn test_align_union() {
+        let sources = vec![
+            ReaderSource::from_read(Cursor::new("a,b\n1,2\n"), "first"),
+            ReaderSource::from_read(Cursor::new("c,a\n3,4\n"), "second"),
+        ];
+        let input_stream = InputStream::from_readers(sources, UTF_8)
+            .with_alignment(HeaderAlignment::Union, "NA");
+
+        assert_eq!(
+            *input_stream.headers(),
+            Row::from(vec!["a", "b", "c", SOURCE_FIELD])
+        );
+
+        let rows: Vec<Row> = input_stream.map(|r| r.unwrap()).collect();
+
+        assert_eq!(
+            rows,
+            vec![
+                Row::from(vec!["1", "2", "NA", "first"]),
+                Row::from(vec!["4", "NA", "3", "second"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_align_union_unreadable_source() {
+        struct Broken;
+
+        impl Read for Broken {
+            fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("broken"))
+            }
+        }
+
+        let sources = vec![
+            ReaderSource::from_read(Cursor::new("a\n1\n"), "first"),
+            ReaderSource::from_read(Broken, "broken"),
+            ReaderSource::from_read(Cursor::new("b\n2\n"), "last"),
+        ];
+        let mut input_stream = InputStream::from_readers(sources, UTF_8)
+            .with_alignment(HeaderAlignment::Union, "");
+
+        assert_eq!(*input_stream.headers(), Row::from(vec!["a", "b", SOURCE_FIELD]));
+        assert_eq!(input_stream.next().unwrap().unwrap(), Row::from(vec!["1", "", "first"]));
+
+        match input_stream.next() {
+            Some(Err(Error::Read(name, _, _))) => assert_eq!(name, "broken"),
+            x => unreachable!("{:?}", x),
+        }
+
+        assert_eq!(input_stream.next().unwrap().unwrap(), Row::from(vec!["", "2", "last"]));
+        assert!(input_stream.next().is_none());
+    }
+
+    #[test]
+    fn test_align_schema() {
+        let filenames = ["test/assets/1.csv", "test/assets/3.csv"];
+        let input_stream = InputStream::from_readers(
+            filenames.iter().map(|f| ReaderSource::from_path(f).unwrap()),
+            UTF_8,
+        ).with_alignment(HeaderAlignment::Schema(vec!["b".to_string(), "c".to_string()]), "NA");
+
+        assert_eq!(
+            *input_stream.headers(),
+            Row::from(vec!["b", "c", SOURCE_FIELD])
+        );
+
+        let rows: Vec<Row> = input_stream.map(|r| r.unwrap()).collect();
+
+        assert_eq!(rows[0], Row::from(vec!["3", "NA", "test/assets/1.csv"]));
+        assert_eq!(rows[2], Row::from(vec!["2", "3", "test/assets/3.csv"]));
+    }
+
     #[test]
     fn detects_inconsistent_headers() {
         let filenames = ["test/assets/1.csv", "test/assets/3.csv"];
@@ -213,7 +1141,10 @@ mod tests {
         );
 
         match input_stream.skip(2).next() {
-            Some(Err(Error::InconsistentHeaders)) => { () }
+            Some(Err(Error::InconsistentSourceHeaders(name, position))) => {
+                assert_eq!(name, "test/assets/3.csv");
+                assert_eq!(position.line(), 1);
+            }
 
             x => unreachable!("{:?}", x),
         }
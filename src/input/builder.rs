@@ -0,0 +1,298 @@
+use csv::{ByteRecord, Trim};
+use encoding::EncodingRef;
+use encoding::all::{UTF_8, WINDOWS_1252};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use std::str;
+
+use super::{ReaderSource, compression::decompress};
+
+/// Delimiters considered when sniffing
+const SNIFF_CANDIDATES: [u8; 4] = [b',', b';', b'\t', b'|'];
+
+/// Number of lines read when sniffing the delimiter
+const SNIFF_LINES: usize = 10;
+
+/// Byte order mark of UTF-8 encoded data
+const UTF8_BOM: &[u8] = b"\xef\xbb\xbf";
+
+/// Where the column names of a source come from.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum SourceHeaders {
+    /// The first line of the source.
+    #[default]
+    FirstLine,
+
+    /// The given names. The first line is data.
+    Names(Vec<String>),
+
+    /// Names `col1` to `colN`, where `N` is the number of fields of the
+    /// first line, which is data.
+    Generated,
+}
+
+/// Builds a [ReaderSource](struct.ReaderSource.html) with a CSV dialect
+/// other than the default comma separated one.
+///
+/// ```rust
+/// use csvsc::ReaderSource;
+///
+/// let source = ReaderSource::builder()
+///     .delimiter(b';')
+///     .comment(Some(b'#'))
+///     .from_path("test/assets/semicolon.csv")
+///     .unwrap();
+/// ```
+#[derive(Clone)]
+pub struct ReaderSourceBuilder {
+    delimiter: u8,
+    quote: u8,
+    escape: Option<u8>,
+    comment: Option<u8>,
+    trim: bool,
+    flexible: bool,
+    sniff: bool,
+    headers: SourceHeaders,
+    encoding: Option<EncodingRef>,
+    detect_encoding: bool,
+}
+
+impl fmt::Debug for ReaderSourceBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ReaderSourceBuilder")
+            .field("delimiter", &self.delimiter)
+            .field("quote", &self.quote)
+            .field("escape", &self.escape)
+            .field("comment", &self.comment)
+            .field("trim", &self.trim)
+            .field("flexible", &self.flexible)
+            .field("sniff", &self.sniff)
+            .field("headers", &self.headers)
+            .field("encoding", &self.encoding.map(|e| e.name()))
+            .field("detect_encoding", &self.detect_encoding)
+            .finish()
+    }
+}
+
+impl Default for ReaderSourceBuilder {
+    fn default() -> ReaderSourceBuilder {
+        ReaderSourceBuilder {
+            delimiter: b',',
+            quote: b'"',
+            escape: None,
+            comment: None,
+            trim: false,
+            flexible: false,
+            sniff: false,
+            headers: SourceHeaders::FirstLine,
+            encoding: None,
+            detect_encoding: false,
+        }
+    }
+}
+
+impl ReaderSourceBuilder {
+    pub fn new() -> ReaderSourceBuilder {
+        Default::default()
+    }
+
+    /// The field delimiter, `,` by default
+    pub fn delimiter(self, delimiter: u8) -> ReaderSourceBuilder {
+        ReaderSourceBuilder { delimiter, ..self }
+    }
+
+    /// The quote character, `"` by default
+    pub fn quote(self, quote: u8) -> ReaderSourceBuilder {
+        ReaderSourceBuilder { quote, ..self }
+    }
+
+    /// The character used to escape quotes inside quoted fields. By default
+    /// quotes are escaped by doubling them.
+    pub fn escape(self, escape: Option<u8>) -> ReaderSourceBuilder {
+        ReaderSourceBuilder { escape, ..self }
+    }
+
+    /// Lines starting with this character are ignored
+    pub fn comment(self, comment: Option<u8>) -> ReaderSourceBuilder {
+        ReaderSourceBuilder { comment, ..self }
+    }
+
+    /// Removes leading and trailing whitespace from headers and fields
+    pub fn trim(self, trim: bool) -> ReaderSourceBuilder {
+        ReaderSourceBuilder { trim, ..self }
+    }
+
+    /// Accepts rows with a different number of fields than the headers. Short
+    /// rows are filled with empty fields, long ones are still an error.
+    pub fn flexible(self, flexible: bool) -> ReaderSourceBuilder {
+        ReaderSourceBuilder { flexible, ..self }
+    }
+
+    /// Guesses the delimiter from the first lines of the file among `,`, `;`,
+    /// tab and `|`, falling back to the configured one if none fits.
+    pub fn sniff_delimiter(self, sniff: bool) -> ReaderSourceBuilder {
+        ReaderSourceBuilder { sniff, ..self }
+    }
+
+    /// Sets where the column names come from, by default the first line
+    pub fn headers(self, headers: SourceHeaders) -> ReaderSourceBuilder {
+        ReaderSourceBuilder { headers, ..self }
+    }
+
+    /// The encoding of the source. By default the one given to the
+    /// [InputStream](struct.InputStream.html) is used.
+    pub fn encoding(self, encoding: EncodingRef) -> ReaderSourceBuilder {
+        ReaderSourceBuilder { encoding: Some(encoding), ..self }
+    }
+
+    /// Guesses the encoding from the first bytes of the file: UTF-8 if they
+    /// are valid UTF-8 or start with a byte order mark, WINDOWS-1252
+    /// otherwise. Ignored if an encoding is set. The chosen one is reported
+    /// by [ReaderSource::encoding](struct.ReaderSource.html#method.encoding).
+    pub fn detect_encoding(self, detect_encoding: bool) -> ReaderSourceBuilder {
+        ReaderSourceBuilder { detect_encoding, ..self }
+    }
+
+    /// Builds a source reading the given file. Files compressed with gzip,
+    /// zstd or bzip2 are decompressed on the fly if the corresponding cargo
+    /// feature is enabled. The compression is detected by the first bytes of
+    /// the file or its extension.
+    pub fn from_path<P: AsRef<Path>>(&self, path: P) -> Result<ReaderSource, csv::Error> {
+        let file = decompress(File::open(&path)?, path.as_ref())?;
+
+        self.from_read(file, &path.as_ref().to_string_lossy())
+    }
+
+    /// Builds a source reading from anything implementing `io::Read`,
+    /// identified by the given label. A UTF-8 byte order mark at the start
+    /// is skipped.
+    pub fn from_read<R: Read + Send + 'static>(&self, read: R, name: &str) -> Result<ReaderSource, csv::Error> {
+        let mut read = BufReader::new(read);
+        let bom = read.fill_buf()?.starts_with(UTF8_BOM);
+
+        if bom {
+            read.consume(UTF8_BOM.len());
+        }
+
+        let encoding = match self.encoding {
+            Some(encoding) => Some(encoding),
+            None if bom && self.detect_encoding => Some(UTF_8 as EncodingRef),
+            None if self.detect_encoding => Some(detect_encoding(read.fill_buf()?)),
+            None => None,
+        };
+        let delimiter = if self.sniff {
+            sniff(read.fill_buf()?, self.quote, self.comment).unwrap_or(self.delimiter)
+        } else {
+            self.delimiter
+        };
+
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(self.headers == SourceHeaders::FirstLine)
+            .delimiter(delimiter)
+            .quote(self.quote)
+            .escape(self.escape)
+            .double_quote(self.escape.is_none())
+            .comment(self.comment)
+            .trim(if self.trim { Trim::All } else { Trim::None })
+            .flexible(self.flexible)
+            .from_reader(Box::new(read) as Box<dyn Read + Send>);
+
+        let names = match self.headers {
+            SourceHeaders::FirstLine => None,
+            SourceHeaders::Names(ref names) => Some(ByteRecord::from(names.clone())),
+            SourceHeaders::Generated => {
+                // without headers this peeks the first record, that is still
+                // returned as data
+                let len = reader.byte_headers()?.len();
+                let names: Vec<String> = (1..=len).map(|i| format!("col{}", i)).collect();
+
+                Some(ByteRecord::from(names))
+            },
+        };
+
+        let mut source = ReaderSource::from_reader(reader, name);
+
+        source.flexible = self.flexible;
+        source.names = names;
+        source.encoding = encoding;
+
+        Ok(source)
+    }
+}
+
+/// Counts the candidate delimiters outside quotes in the first lines of the
+/// given data and returns the one found the same, non zero, number of times
+/// in every line. If several fit the most frequent wins.
+fn sniff(data: &[u8], quote: u8, comment: Option<u8>) -> Option<u8> {
+    let mut counts: Vec<[usize; SNIFF_CANDIDATES.len()]> = Vec::with_capacity(SNIFF_LINES);
+
+    // the last line is probably cut
+    let lines = match data.iter().rposition(|b| *b == b'\n') {
+        Some(end) => &data[..end],
+        None => data,
+    };
+
+    for line in lines.split(|b| *b == b'\n').take(SNIFF_LINES) {
+        if line.is_empty() || comment.map(|c| line[0] == c).unwrap_or(false) {
+            continue;
+        }
+
+        let mut line_counts = [0; SNIFF_CANDIDATES.len()];
+        let mut quoted = false;
+
+        for &byte in line {
+            if byte == quote {
+                quoted = !quoted;
+            } else if !quoted {
+                if let Some(i) = SNIFF_CANDIDATES.iter().position(|c| *c == byte) {
+                    line_counts[i] += 1;
+                }
+            }
+        }
+
+        counts.push(line_counts);
+    }
+
+    let first = match counts.first() {
+        Some(first) => *first,
+        None => return None,
+    };
+
+    SNIFF_CANDIDATES.iter()
+        .enumerate()
+        .filter(|(i, _)| first[*i] > 0 && counts.iter().all(|c| c[*i] == first[*i]))
+        .max_by_key(|(i, _)| first[*i])
+        .map(|(_, c)| *c)
+}
+
+/// UTF-8 if the given data is valid UTF-8, except maybe for a character cut
+/// at the end, WINDOWS-1252 otherwise.
+fn detect_encoding(data: &[u8]) -> EncodingRef {
+    match str::from_utf8(data) {
+        Ok(_) => UTF_8,
+        Err(e) if e.error_len().is_none() => UTF_8,
+        Err(_) => WINDOWS_1252,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{detect_encoding, sniff};
+
+    #[test]
+    fn test_sniff() {
+        assert_eq!(sniff(b"# comment\nname;price\n'a; b';12,5\n", b'\'', Some(b'#')), Some(b';'));
+        assert_eq!(sniff(b"a,b\n1,3\n5,2\n", b'"', None), Some(b','));
+        assert_eq!(sniff(b"a\tb\n1\t2\n3\t4", b'"', None), Some(b'\t'));
+        assert_eq!(sniff(b"a\n1\n", b'"', None), None);
+    }
+
+    #[test]
+    fn test_detect_encoding() {
+        assert_eq!(detect_encoding("name\nárbol\n".as_bytes()).name(), "utf-8");
+        assert_eq!(detect_encoding(b"name\n\xc3").name(), "utf-8");
+        assert_eq!(detect_encoding(b"name\n\xe1rbol\n").name(), "windows-1252");
+    }
+}
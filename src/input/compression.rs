@@ -0,0 +1,98 @@
+//! Transparent decompression of input files
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Compression {
+    Gzip,
+    Zstd,
+    Bzip2,
+}
+
+impl Compression {
+    /// Detects the compression from the first bytes of the data or, failing
+    /// that, from the extension of the file.
+    fn detect(path: &Path, head: &[u8]) -> Option<Compression> {
+        if head.starts_with(&[0x1f, 0x8b]) {
+            return Some(Compression::Gzip);
+        }
+
+        if head.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            return Some(Compression::Zstd);
+        }
+
+        // "BZh", the block size and the magic of the first block, or of the
+        // end of the stream if it is empty
+        if head.len() >= 10
+            && head.starts_with(b"BZh")
+            && (b'1'..=b'9').contains(&head[3])
+            && (head[4..10] == [0x31, 0x41, 0x59, 0x26, 0x53, 0x59] || head[4..10] == [0x17, 0x72, 0x45, 0x38, 0x50, 0x90])
+        {
+            return Some(Compression::Bzip2);
+        }
+
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("gz") => Some(Compression::Gzip),
+            Some("zst") => Some(Compression::Zstd),
+            Some("bz2") => Some(Compression::Bzip2),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(not(all(feature = "gzip", feature = "zstd", feature = "bzip2")))]
+fn not_enabled(feature: &str) -> io::Error {
+    io::Error::other(format!("reading this file requires the `{}` feature of csvsc", feature))
+}
+
+/// Wraps the given file in a decoder if it is compressed.
+pub fn decompress<R: Read + Send + 'static>(read: R, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+    let mut read = BufReader::new(read);
+
+    match Compression::detect(path, read.fill_buf()?) {
+        None => Ok(Box::new(read)),
+
+        #[cfg(feature = "gzip")]
+        Some(Compression::Gzip) => Ok(Box::new(flate2::bufread::MultiGzDecoder::new(read))),
+        #[cfg(not(feature = "gzip"))]
+        Some(Compression::Gzip) => Err(not_enabled("gzip")),
+
+        #[cfg(feature = "zstd")]
+        Some(Compression::Zstd) => Ok(Box::new(zstd::stream::read::Decoder::with_buffer(read)?)),
+        #[cfg(not(feature = "zstd"))]
+        Some(Compression::Zstd) => Err(not_enabled("zstd")),
+
+        #[cfg(feature = "bzip2")]
+        Some(Compression::Bzip2) => Ok(Box::new(bzip2::bufread::MultiBzDecoder::new(read))),
+        #[cfg(not(feature = "bzip2"))]
+        Some(Compression::Bzip2) => Err(not_enabled("bzip2")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+    use std::path::Path;
+
+    use super::{Compression, decompress};
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(Compression::detect(Path::new("a.csv"), b"\x1f\x8b\x08"), Some(Compression::Gzip));
+        assert_eq!(Compression::detect(Path::new("a.csv"), b"BZh91AY&SY\x00"), Some(Compression::Bzip2));
+        assert_eq!(Compression::detect(Path::new("a.csv"), b"BZh,name\n1,a\n"), None);
+        assert_eq!(Compression::detect(Path::new("a.csv"), b"BZh9"), None);
+        assert_eq!(Compression::detect(Path::new("a.csv.zst"), b""), Some(Compression::Zstd));
+        assert_eq!(Compression::detect(Path::new("a.csv"), b"a,b\n"), None);
+    }
+
+    #[test]
+    fn test_plain_csv_starting_like_bzip2() {
+        let mut read = decompress(Cursor::new("BZhid,name\n1,a\n"), Path::new("a.csv")).unwrap();
+        let mut data = String::new();
+
+        read.read_to_string(&mut data).unwrap();
+
+        assert_eq!(data, "BZhid,name\n1,a\n");
+    }
+}
@@ -0,0 +1,367 @@
+//! Reading rows from JSON Lines data
+use csv::Position;
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use super::{STDIN_LABEL, compression::decompress};
+use crate::{
+    Row, SOURCE_FIELD, Headers, RowStream,
+    error::{Error, RowResult},
+};
+
+/// A source of JSON Lines data, one object per line. Like a
+/// [ReaderSource](struct.ReaderSource.html) it has a label that is used as
+/// the value of the `SOURCE_FIELD` column.
+pub struct JsonSource {
+    reader: BufReader<Box<dyn Read + Send>>,
+    name: String,
+}
+
+impl JsonSource {
+    pub fn from_read<R: Read + Send + 'static>(read: R, name: &str) -> JsonSource {
+        JsonSource {
+            reader: BufReader::new(Box::new(read) as Box<dyn Read + Send>),
+            name: name.to_string(),
+        }
+    }
+
+    /// Reads the given file, decompressing it if needed, see
+    /// [ReaderSourceBuilder::from_path](struct.ReaderSourceBuilder.html#method.from_path).
+    pub fn from_path<P: AsRef<Path>>(path: P) -> io::Result<JsonSource> {
+        let file = decompress(File::open(&path)?, path.as_ref())?;
+
+        Ok(JsonSource::from_read(file, &path.as_ref().to_string_lossy()))
+    }
+
+    pub fn from_stdin() -> JsonSource {
+        JsonSource::from_read(io::stdin(), STDIN_LABEL)
+    }
+}
+
+/// How the columns of a [JsonLinesStream](struct.JsonLinesStream.html) are
+/// decided.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JsonFields {
+    /// Exactly these columns. Other keys are ignored.
+    Declared(Vec<String>),
+
+    /// The union of the keys of the first given number of objects, in the
+    /// order they were first seen.
+    Infer(usize),
+}
+
+/// An object with its nested objects flattened, and the label of its source
+type Flat = (Vec<(String, String)>, String);
+
+/// Turns an object into a list of fields. Nested objects are flattened using
+/// dotted names, arrays are kept as JSON text and `null` becomes an empty
+/// field.
+fn flatten(prefix: &str, object: serde_json::Map<String, Value>, fields: &mut Vec<(String, String)>) {
+    for (key, value) in object {
+        let name = if prefix.is_empty() {
+            key
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+
+        match value {
+            Value::Object(inner) => flatten(&name, inner, fields),
+            Value::String(s) => fields.push((name, s)),
+            Value::Null => fields.push((name, String::new())),
+            other => fields.push((name, other.to_string())),
+        }
+    }
+}
+
+/// A stream of rows read from JSON Lines sources. Each line must be a JSON
+/// object, empty lines are skipped. A source that fails to be read is
+/// reported once and not read any further.
+///
+/// ```rust
+/// use std::io::Cursor;
+/// use csvsc::{JsonFields, JsonLinesStream, JsonSource, RowStream};
+///
+/// let stream = JsonLinesStream::new(
+///     vec![JsonSource::from_read(Cursor::new("{\"a\": {\"b\": 1}}\n"), "memory")],
+///     JsonFields::Infer(10),
+/// );
+///
+/// assert_eq!(stream.headers().as_row(), &csvsc::Row::from(vec!["a.b", "_source"]));
+/// ```
+pub struct JsonLinesStream {
+    sources: VecDeque<JsonSource>,
+    /// Where the next line starts in the current source
+    position: Position,
+    /// Objects already read while infering the headers
+    buffered: VecDeque<Result<Flat, Error>>,
+    headers: Headers,
+}
+
+impl JsonLinesStream {
+    pub fn new<I>(sources: I, fields: JsonFields) -> JsonLinesStream
+    where
+        I: IntoIterator<Item = JsonSource>,
+    {
+        let mut stream = JsonLinesStream {
+            sources: sources.into_iter().collect(),
+            position: Position::new(),
+            buffered: VecDeque::new(),
+            headers: Headers::from_row(Row::new()),
+        };
+
+        let names = match fields {
+            JsonFields::Declared(names) => names,
+            JsonFields::Infer(count) => {
+                let mut names: Vec<String> = Vec::new();
+                // invalid lines don't count
+                let mut parsed = 0;
+
+                while parsed < count {
+                    match stream.next_object() {
+                        Some(Ok(object)) => {
+                            parsed += 1;
+
+                            for (key, _) in object.0.iter() {
+                                if !names.contains(key) {
+                                    names.push(key.clone());
+                                }
+                            }
+
+                            stream.buffered.push_back(Ok(object));
+                        },
+                        Some(Err(e)) => stream.buffered.push_back(Err(e)),
+                        None => break,
+                    }
+                }
+
+                names
+            },
+        };
+
+        let mut header_row = Row::with_capacity(0, names.len() + 1);
+
+        for name in names.iter() {
+            header_row.push_field(name);
+        }
+
+        header_row.push_field(SOURCE_FIELD);
+        stream.headers = Headers::from_row(header_row);
+
+        stream
+    }
+
+    /// Reads the next object from the sources
+    fn next_object(&mut self) -> Option<Result<Flat, Error>> {
+        let mut line = Vec::new();
+
+        loop {
+            let source = self.sources.front_mut()?;
+
+            line.clear();
+
+            match source.reader.read_until(b'\n', &mut line) {
+                Ok(0) => {
+                    self.sources.pop_front();
+                    self.position = Position::new();
+
+                    continue;
+                },
+                Ok(_) => {},
+                Err(e) => {
+                    // the reader may fail forever, so give up on the source
+                    let source = self.sources.pop_front()?;
+
+                    self.position = Position::new();
+
+                    return Some(Err(Error::Io(PathBuf::from(source.name), e)));
+                },
+            }
+
+            let position = self.position.clone();
+
+            self.position
+                .set_byte(position.byte() + line.len() as u64)
+                .set_line(position.line() + 1)
+                .set_record(position.record() + 1);
+
+            if line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+
+            // invalid UTF-8 is reported by serde_json like any other syntax error
+            let object = match serde_json::from_slice(&line) {
+                Ok(Value::Object(object)) => object,
+                Ok(_) => return Some(Err(Error::InvalidJson(
+                    source.name.clone(), position, "expected an object".to_string(),
+                ))),
+                Err(e) => return Some(Err(Error::InvalidJson(
+                    source.name.clone(), position, e.to_string(),
+                ))),
+            };
+            let mut fields = Vec::new();
+
+            flatten("", object, &mut fields);
+
+            return Some(Ok((fields, source.name.clone())));
+        }
+    }
+}
+
+impl RowStream for JsonLinesStream {
+    fn headers(&self) -> &Headers {
+        &self.headers
+    }
+}
+
+impl Iterator for JsonLinesStream {
+    type Item = RowResult;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let object = match self.buffered.pop_front() {
+            Some(object) => object,
+            None => self.next_object()?,
+        };
+
+        Some(object.map(|(fields, name)| {
+            let mut fields: HashMap<String, String> = fields.into_iter().collect();
+            let mut row = Row::with_capacity(0, self.headers.len());
+
+            // the last header is the source
+            for colname in self.headers.iter().take(self.headers.len() - 1) {
+                row.push_field(fields.remove(colname).as_deref().unwrap_or(""));
+            }
+
+            row.push_field(&name);
+
+            row
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Cursor, Read};
+
+    use super::{JsonFields, JsonLinesStream, JsonSource};
+    use crate::{Row, SOURCE_FIELD, RowStream, error::Error};
+
+    const DATA: &str = "{\"id\": 1, \"user\": {\"name\": \"ana\", \"age\": 30}}\n\n{\"id\": 2, \"tags\": [\"a\"], \"extra\": null}\nnot json\n{\"id\": 3, \"late\": true}\n";
+
+    #[test]
+    fn test_json_infer() {
+        let stream = JsonLinesStream::new(
+            vec![JsonSource::from_read(Cursor::new(DATA), "data")],
+            JsonFields::Infer(2),
+        );
+
+        assert_eq!(
+            stream.headers().as_row(),
+            &Row::from(vec!["id", "user.name", "user.age", "tags", "extra", SOURCE_FIELD])
+        );
+
+        let mut stream = stream.into_iter();
+
+        assert_eq!(stream.next().unwrap().unwrap(), Row::from(vec!["1", "ana", "30", "", "", "data"]));
+        assert_eq!(stream.next().unwrap().unwrap(), Row::from(vec!["2", "", "", "[\"a\"]", "", "data"]));
+
+        match stream.next() {
+            Some(Err(Error::InvalidJson(name, position, _))) => {
+                assert_eq!(name, "data");
+                assert_eq!(position.line(), 4);
+                assert_eq!(position.byte(), 87);
+            },
+            x => unreachable!("{:?}", x),
+        }
+
+        assert_eq!(stream.next().unwrap().unwrap(), Row::from(vec!["3", "", "", "", "", "data"]));
+        assert!(stream.next().is_none());
+    }
+
+    #[test]
+    fn test_json_declared() {
+        let stream = JsonLinesStream::new(
+            vec![
+                JsonSource::from_read(Cursor::new(DATA), "data"),
+                JsonSource::from_read(Cursor::new("{\"late\": false}"), "more"),
+            ],
+            JsonFields::Declared(vec!["late".to_string(), "user.name".to_string()]),
+        );
+
+        let rows: Vec<Row> = stream.filter_map(|r| r.ok()).collect();
+
+        assert_eq!(
+            rows,
+            vec![
+                Row::from(vec!["", "ana", "data"]),
+                Row::from(vec!["", "", "data"]),
+                Row::from(vec!["true", "", "data"]),
+                Row::from(vec!["false", "", "more"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_json_line_numbers_after_invalid_utf8() {
+        let data = &b"{\"a\": 1}\n\xff\n{\"a\": 2}\nnot json\n"[..];
+        let mut stream = JsonLinesStream::new(
+            vec![JsonSource::from_read(Cursor::new(data), "data")],
+            JsonFields::Declared(vec!["a".to_string()]),
+        );
+
+        stream.next().unwrap().unwrap();
+
+        match stream.next() {
+            Some(Err(Error::InvalidJson(_, position, _))) => assert_eq!(position.line(), 2),
+            x => unreachable!("{:?}", x),
+        }
+
+        stream.next().unwrap().unwrap();
+
+        match stream.next() {
+            Some(Err(Error::InvalidJson(_, position, _))) => assert_eq!(position.line(), 4),
+            x => unreachable!("{:?}", x),
+        }
+    }
+
+    #[test]
+    fn test_json_failing_source_is_dropped() {
+        struct Broken;
+
+        impl Read for Broken {
+            fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("broken"))
+            }
+        }
+
+        let mut stream = JsonLinesStream::new(
+            vec![
+                JsonSource::from_read(Broken, "broken"),
+                JsonSource::from_read(Cursor::new("{\"a\": 1}\n"), "data"),
+            ],
+            JsonFields::Infer(3),
+        );
+
+        assert_eq!(stream.headers().as_row(), &Row::from(vec!["a", SOURCE_FIELD]));
+
+        match stream.next() {
+            Some(Err(Error::Io(path, _))) => assert_eq!(path.to_str(), Some("broken")),
+            x => unreachable!("{:?}", x),
+        }
+
+        assert_eq!(stream.next().unwrap().unwrap(), Row::from(vec!["1", "data"]));
+        assert!(stream.next().is_none());
+    }
+
+    #[test]
+    fn test_json_infer_skips_invalid_lines() {
+        let stream = JsonLinesStream::new(
+            vec![JsonSource::from_read(Cursor::new("not json\n{\"a\": 1}\n{\"b\": 2}\n"), "data")],
+            JsonFields::Infer(2),
+        );
+
+        assert_eq!(stream.headers().as_row(), &Row::from(vec!["a", "b", SOURCE_FIELD]));
+    }
+}
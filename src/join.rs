@@ -0,0 +1,498 @@
+//! Utilities for joining two streams by the values of some columns
+use std::collections::HashMap;
+use std::iter::Chain;
+use std::vec;
+
+use crate::{
+    Headers, Row, RowStream,
+    error::{Error, RowResult},
+};
+
+#[derive(Debug)]
+pub enum BuildError {
+    /// A join key is missing from the left stream
+    LeftKeyNotFound(String),
+
+    /// A join key is missing from the right stream
+    RightKeyNotFound(String),
+
+    /// Two columns of the result would have the same name even after adding
+    /// the suffixes
+    DuplicatedHeader(String),
+}
+
+/// The kind of join performed by a [Join](struct.Join.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinKind {
+    /// Only rows of the left stream that have a match in the right stream,
+    /// once per match.
+    Inner,
+
+    /// Every row of the left stream, once per match in the right stream or
+    /// once with empty right columns if it has no match.
+    Left,
+
+    /// Only rows of the left stream that have no match in the right stream.
+    /// Only the left columns are emitted.
+    Anti,
+}
+
+/// Joins the rows of two streams whose join columns have equal values.
+///
+/// The output has the columns of the left stream followed by those of the
+/// right stream except for the join columns. Columns present in both streams
+/// get a suffix, `_left` and `_right` by default, see
+/// [with_suffixes](#method.with_suffixes).
+///
+/// Both streams are read in turns until one of them ends. That one, the
+/// smaller, is kept in a hash table and the other is streamed against it.
+/// Because of this the order of the rows follows the bigger stream, and when
+/// the left stream is the smaller one the rows of a left or anti join that
+/// have no match are emitted at the end.
+///
+/// Errors found while reading the smaller stream are emitted first, those of
+/// the bigger stream are emitted as they are found.
+pub struct Join<L, R> {
+    left: L,
+    right: R,
+    on: Vec<String>,
+    kind: JoinKind,
+    left_headers: Headers,
+    right_headers: Headers,
+    headers: Headers,
+}
+
+impl<L, R> Join<L, R>
+where
+    L: RowStream,
+    R: RowStream,
+{
+    pub fn new(left: L, right: R, on: &[&str], kind: JoinKind) -> Result<Join<L, R>, BuildError> {
+        let left_headers = left.headers().clone();
+        let right_headers = right.headers().clone();
+
+        for col in on {
+            if !left_headers.contains_key(col) {
+                return Err(BuildError::LeftKeyNotFound(col.to_string()));
+            }
+
+            if !right_headers.contains_key(col) {
+                return Err(BuildError::RightKeyNotFound(col.to_string()));
+            }
+        }
+
+        let on: Vec<String> = on.iter().map(|c| c.to_string()).collect();
+        let headers = merge_headers(&left_headers, &right_headers, &on, kind, "_left", "_right")?;
+
+        Ok(Join {
+            left,
+            right,
+            on,
+            kind,
+            left_headers,
+            right_headers,
+            headers,
+        })
+    }
+
+    /// Sets the suffixes added to the names of the columns present in both
+    /// streams.
+    pub fn with_suffixes(self, left: &str, right: &str) -> Result<Join<L, R>, BuildError> {
+        let headers = merge_headers(&self.left_headers, &self.right_headers, &self.on, self.kind, left, right)?;
+
+        Ok(Join {
+            headers,
+            ..self
+        })
+    }
+}
+
+fn merge_headers(
+    left: &Headers,
+    right: &Headers,
+    on: &[String],
+    kind: JoinKind,
+    left_suffix: &str,
+    right_suffix: &str,
+) -> Result<Headers, BuildError> {
+    if kind == JoinKind::Anti {
+        return Ok(left.clone());
+    }
+
+    let right_cols: Vec<&str> = right.iter().filter(|c| !on.iter().any(|k| k == c)).collect();
+    let mut headers = Headers::from_row(Row::new());
+
+    for col in left.iter() {
+        let name = if right_cols.contains(&col) {
+            format!("{}{}", col, left_suffix)
+        } else {
+            col.to_string()
+        };
+
+        if headers.add(&name).is_err() {
+            return Err(BuildError::DuplicatedHeader(name));
+        }
+    }
+
+    for col in right_cols {
+        let name = if left.contains_key(col) {
+            format!("{}{}", col, right_suffix)
+        } else {
+            col.to_string()
+        };
+
+        if headers.add(&name).is_err() {
+            return Err(BuildError::DuplicatedHeader(name));
+        }
+    }
+
+    Ok(headers)
+}
+
+/// Indexes of the right columns that are copied to the output
+fn right_indexes(right: &Headers, on: &[String]) -> Vec<usize> {
+    right.iter()
+        .enumerate()
+        .filter(|(_, c)| !on.iter().any(|k| k == c))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// The stream that is not kept in memory, with the rows read while looking
+/// for the smaller stream put back in front.
+enum Probe<L, R> {
+    Left(Chain<vec::IntoIter<RowResult>, L>),
+    Right(Chain<vec::IntoIter<RowResult>, R>),
+}
+
+impl<L, R> Iterator for Probe<L, R>
+where
+    L: Iterator<Item = RowResult>,
+    R: Iterator<Item = RowResult>,
+{
+    type Item = RowResult;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Probe::Left(iter) => iter.next(),
+            Probe::Right(iter) => iter.next(),
+        }
+    }
+}
+
+pub struct IntoIter<L, R> {
+    probe: Probe<L, R>,
+    probe_headers: Headers,
+    /// Rows of the smaller stream, and whether they matched a row of the
+    /// bigger one
+    table: Vec<(Row, bool)>,
+    index: HashMap<Vec<String>, Vec<usize>>,
+    on: Vec<String>,
+    kind: JoinKind,
+    right_indexes: Vec<usize>,
+    pending: vec::IntoIter<RowResult>,
+    unmatched: Option<vec::IntoIter<RowResult>>,
+}
+
+impl<L, R> IntoIter<L, R> {
+    fn build_is_left(&self) -> bool {
+        match self.probe {
+            Probe::Left(_) => false,
+            Probe::Right(_) => true,
+        }
+    }
+
+    fn join_rows(&self, left: &Row, right: Option<&Row>) -> Row {
+        let mut row = Row::with_capacity(left.as_slice().len(), left.len() + self.right_indexes.len());
+
+        for field in left.iter() {
+            row.push_field(field);
+        }
+
+        if self.kind == JoinKind::Anti {
+            return row;
+        }
+
+        for i in self.right_indexes.iter() {
+            row.push_field(right.and_then(|r| r.get(*i)).unwrap_or(""));
+        }
+
+        row
+    }
+
+    /// Computes the output for one row of the bigger stream
+    fn probe_row(&mut self, row: Row) -> Vec<RowResult> {
+        let key = match self.probe_headers.key(&row, &self.on) {
+            Ok(key) => key,
+            Err(col) => return vec![Err(Error::ColumnNotFound(col))],
+        };
+        let matches = self.index.get(&key).cloned().unwrap_or_default();
+
+        if self.build_is_left() {
+            for i in matches.iter() {
+                self.table[*i].1 = true;
+            }
+
+            match self.kind {
+                JoinKind::Inner | JoinKind::Left => matches.iter()
+                    .map(|i| Ok(self.join_rows(&self.table[*i].0, Some(&row))))
+                    .collect(),
+                JoinKind::Anti => Vec::new(),
+            }
+        } else {
+            match self.kind {
+                JoinKind::Inner | JoinKind::Left if !matches.is_empty() => matches.iter()
+                    .map(|i| Ok(self.join_rows(&row, Some(&self.table[*i].0))))
+                    .collect(),
+                JoinKind::Inner => Vec::new(),
+                JoinKind::Left => vec![Ok(self.join_rows(&row, None))],
+                JoinKind::Anti if matches.is_empty() => vec![Ok(row)],
+                JoinKind::Anti => Vec::new(),
+            }
+        }
+    }
+
+    /// Rows of the left stream that never matched, emitted after the right
+    /// stream is consumed when the left one is the smaller
+    fn unmatched_rows(&mut self) -> Vec<RowResult> {
+        if !self.build_is_left() || self.kind == JoinKind::Inner {
+            return Vec::new();
+        }
+
+        let table = std::mem::take(&mut self.table);
+
+        table.into_iter()
+            .filter(|(_, matched)| !matched)
+            .map(|(row, _)| Ok(self.join_rows(&row, None)))
+            .collect()
+    }
+}
+
+impl<L, R> Iterator for IntoIter<L, R>
+where
+    L: Iterator<Item = RowResult>,
+    R: Iterator<Item = RowResult>,
+{
+    type Item = RowResult;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.pending.next() {
+                return Some(item);
+            }
+
+            if let Some(ref mut unmatched) = self.unmatched {
+                return unmatched.next();
+            }
+
+            match self.probe.next() {
+                Some(Ok(row)) => self.pending = self.probe_row(row).into_iter(),
+                Some(Err(e)) => return Some(Err(e)),
+                None => self.unmatched = Some(self.unmatched_rows().into_iter()),
+            }
+        }
+    }
+}
+
+impl<L, R> IntoIterator for Join<L, R>
+where
+    L: RowStream,
+    R: RowStream,
+{
+    type Item = RowResult;
+
+    type IntoIter = IntoIter<L::IntoIter, R::IntoIter>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let mut left = self.left.into_iter();
+        let mut right = self.right.into_iter();
+        let mut left_rows = Vec::new();
+        let mut right_rows = Vec::new();
+
+        // read both streams in turns until the smaller one ends
+        let (build, build_headers, probe, probe_headers) = loop {
+            match left.next() {
+                Some(item) => left_rows.push(item),
+                None => break (
+                    left_rows,
+                    self.left_headers,
+                    Probe::Right(right_rows.into_iter().chain(right)),
+                    self.right_headers.clone(),
+                ),
+            }
+
+            match right.next() {
+                Some(item) => right_rows.push(item),
+                None => break (
+                    right_rows,
+                    self.right_headers.clone(),
+                    Probe::Left(left_rows.into_iter().chain(left)),
+                    self.left_headers,
+                ),
+            }
+        };
+
+        let mut errors = Vec::new();
+        let mut table = Vec::with_capacity(build.len());
+        let mut index: HashMap<Vec<String>, Vec<usize>> = HashMap::new();
+
+        for item in build {
+            match item {
+                Ok(row) => match build_headers.key(&row, &self.on) {
+                    Ok(key) => {
+                        index.entry(key).or_default().push(table.len());
+                        table.push((row, false));
+                    },
+                    Err(col) => errors.push(Err(Error::ColumnNotFound(col))),
+                },
+                Err(e) => errors.push(Err(e)),
+            }
+        }
+
+        IntoIter {
+            probe,
+            probe_headers,
+            table,
+            index,
+            right_indexes: right_indexes(&self.right_headers, &self.on),
+            on: self.on,
+            kind: self.kind,
+            pending: errors.into_iter(),
+            unmatched: None,
+        }
+    }
+}
+
+impl<L, R> RowStream for Join<L, R>
+where
+    L: RowStream,
+    R: RowStream,
+{
+    fn headers(&self) -> &Headers {
+        &self.headers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BuildError, Join, JoinKind, Headers, Row, RowStream};
+    use crate::{mock::MockStream, error::Error};
+
+    fn stations() -> MockStream<std::vec::IntoIter<Result<Row, Error>>> {
+        MockStream::from_rows(
+            vec![
+                Ok(Row::from(vec!["station", "name"])),
+                Ok(Row::from(vec!["1", "centro"])),
+                Ok(Row::from(vec!["2", "norte"])),
+            ]
+            .into_iter(),
+        )
+        .unwrap()
+    }
+
+    fn readings() -> MockStream<std::vec::IntoIter<Result<Row, Error>>> {
+        MockStream::from_rows(
+            vec![
+                Ok(Row::from(vec!["station", "name", "temp"])),
+                Ok(Row::from(vec!["2", "a", "30"])),
+                Ok(Row::from(vec!["3", "b", "31"])),
+                Err(Error::InconsistentHeaders),
+                Ok(Row::from(vec!["1", "c", "32"])),
+                Ok(Row::from(vec!["2", "d", "33"])),
+            ]
+            .into_iter(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_inner_join() {
+        let join = Join::new(readings(), stations(), &["station"], JoinKind::Inner).unwrap();
+
+        assert_eq!(
+            *join.headers(),
+            Headers::from_row(Row::from(vec!["station", "name_left", "temp", "name_right"])),
+        );
+
+        let mut join = join.into_iter();
+
+        assert_eq!(join.next().unwrap().unwrap(), Row::from(vec!["2", "a", "30", "norte"]));
+
+        match join.next() {
+            Some(Err(Error::InconsistentHeaders)) => {},
+            _ => unreachable!(),
+        }
+
+        assert_eq!(join.next().unwrap().unwrap(), Row::from(vec!["1", "c", "32", "centro"]));
+        assert_eq!(join.next().unwrap().unwrap(), Row::from(vec!["2", "d", "33", "norte"]));
+        assert!(join.next().is_none());
+    }
+
+    #[test]
+    fn test_left_join() {
+        let join = Join::new(readings(), stations(), &["station"], JoinKind::Left).unwrap()
+            .with_suffixes("", "_station").unwrap();
+
+        assert_eq!(
+            *join.headers(),
+            Headers::from_row(Row::from(vec!["station", "name", "temp", "name_station"])),
+        );
+
+        let results: Vec<Row> = join.into_iter().filter_map(|r| r.ok()).collect();
+
+        assert_eq!(
+            results,
+            vec![
+                Row::from(vec!["2", "a", "30", "norte"]),
+                Row::from(vec!["3", "b", "31", ""]),
+                Row::from(vec!["1", "c", "32", "centro"]),
+                Row::from(vec!["2", "d", "33", "norte"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_join_smaller_left() {
+        let stations = MockStream::from_rows(
+            vec![
+                Ok(Row::from(vec!["station", "name"])),
+                Ok(Row::from(vec!["1", "centro"])),
+                Ok(Row::from(vec!["4", "sur"])),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+
+        let results: Vec<Row> = Join::new(stations, readings(), &["station"], JoinKind::Left).unwrap()
+            .into_iter().filter_map(|r| r.ok()).collect();
+
+        assert_eq!(
+            results,
+            vec![
+                Row::from(vec!["1", "centro", "c", "32"]),
+                Row::from(vec!["4", "sur", "", ""]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_anti_join() {
+        let results: Vec<Row> = Join::new(readings(), stations(), &["station"], JoinKind::Anti).unwrap()
+            .into_iter().filter_map(|r| r.ok()).collect();
+
+        assert_eq!(results, vec![Row::from(vec!["3", "b", "31"])]);
+
+        let results: Vec<Row> = Join::new(stations(), readings(), &["station"], JoinKind::Anti).unwrap()
+            .into_iter().filter_map(|r| r.ok()).collect();
+
+        assert!(results.is_empty());
+    }
+
+    #[test]
+    fn test_join_unexistent_key() {
+        match Join::new(readings(), stations(), &["temp"], JoinKind::Inner) {
+            Err(BuildError::RightKeyNotFound(col)) => assert_eq!(col, "temp"),
+            _ => unreachable!(),
+        }
+    }
+}
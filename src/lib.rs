@@ -81,30 +81,44 @@ mod group;
 mod adjacent_group;
 mod del;
 mod error;
+mod filter;
 mod flush;
 mod headers;
 mod input;
 mod inspect;
+mod join;
+mod map_col;
 mod mock;
 mod reduce;
 mod rename;
 mod row_stream;
+mod select;
+mod sort;
+mod temp;
 pub mod col;
 
 pub use add::{Add, ColSpec};
 pub use add_with::AddWith;
 pub use adjacent_group::AdjacentGroup;
-pub use group::Group;
+pub use group::{Group, GroupHandler, GroupKey, GroupOrder, WithKey};
 pub use del::Del;
 pub use error::{Error, RowResult};
-pub use flush::{Flush, FlushTarget};
+pub use filter::{Filter, FilterSpec, NumOp, RowPredicate};
+pub use flush::{Flush, FlushTarget, OutputFormat, WritePolicy, WRITER_LABEL};
 pub use headers::Headers;
-pub use input::{InputStream, ReaderSource};
+pub use input::{
+    DecodingPolicy, HeaderAlignment, InputStream, JsonFields, JsonLinesStream, JsonSource, ReaderSource,
+    ReaderSourceBuilder, SourceHeaders, STDIN_LABEL,
+};
 pub use inspect::Inspect;
+pub use join::{Join, JoinKind};
+pub use map_col::{ColMapper, MapCol};
 pub use mock::MockStream;
 pub use reduce::Reduce;
 pub use rename::Rename;
 pub use row_stream::RowStream;
+pub use select::{ColSel, Select};
+pub use sort::{Sort, SortKey, SortKind, SortOrder};
 pub use reduce::aggregate;
 
 /// Type alias of csv::StringRecord. Represents a row of data.
@@ -117,6 +131,16 @@ pub type Row = csv::StringRecord;
 /// Es útil en combinación con el procesador [Add](struct.Add.html).
 pub const SOURCE_FIELD: &'static str = "_source";
 
+/// Nombre de la columna opcional con el número de línea de cada registro en
+/// su archivo de entrada. Ver
+/// [InputStream::with_line_column](struct.InputStream.html#method.with_line_column).
+pub const LINE_FIELD: &str = "_line";
+
+/// Nombre de la columna opcional con la posición en bytes donde empieza cada
+/// registro en su archivo de entrada. Ver
+/// [InputStream::with_offset_column](struct.InputStream.html#method.with_offset_column).
+pub const OFFSET_FIELD: &str = "_offset";
+
 /// Things that could go wrong while building a group or adjacent group
 #[derive(Debug)]
 pub enum GroupBuildError {
@@ -0,0 +1,253 @@
+//! Utilities for rewriting existing columns
+use crate::{
+    ColSpec, Headers, Row, RowStream,
+    col,
+    error::{Error, RowResult},
+};
+
+#[derive(Debug)]
+pub enum BuildError {
+    ColumnNotFound(String),
+}
+
+/// Computes the new value of the column rewritten by a
+/// [MapCol](struct.MapCol.html).
+///
+/// It is implemented for closures receiving the current value of the field
+/// and for [ColSpec](enum.ColSpec.html), which computes the value from the
+/// whole row.
+pub trait ColMapper {
+    fn map(&mut self, field: &str, headers: &Headers, row: &Row) -> Result<String, col::BuildError>;
+}
+
+impl<F> ColMapper for F
+where
+    F: FnMut(&str) -> Result<String, col::BuildError>,
+{
+    fn map(&mut self, field: &str, _headers: &Headers, _row: &Row) -> Result<String, col::BuildError> {
+        (self)(field)
+    }
+}
+
+impl ColMapper for ColSpec {
+    fn map(&mut self, _field: &str, headers: &Headers, row: &Row) -> Result<String, col::BuildError> {
+        self.compute(row, headers)
+    }
+}
+
+/// Rewrites a column of each row in place. Headers are not modified.
+pub struct MapCol<I, F> {
+    iter: I,
+    f: F,
+    index: usize,
+    headers: Headers,
+}
+
+impl<I, F> MapCol<I, F>
+where
+    I: RowStream,
+    F: FnMut(&str) -> Result<String, col::BuildError>,
+{
+    pub fn new(iter: I, colname: &str, f: F) -> Result<MapCol<I, F>, BuildError> {
+        MapCol::build(iter, colname, f)
+    }
+}
+
+impl<I> MapCol<I, ColSpec>
+where
+    I: RowStream,
+{
+    /// Rewrites the column named by the spec's `colname` with the value the
+    /// spec computes.
+    pub fn from_spec(iter: I, spec: ColSpec) -> Result<MapCol<I, ColSpec>, BuildError> {
+        let colname = spec.colname().to_string();
+
+        MapCol::build(iter, &colname, spec)
+    }
+}
+
+impl<I, F> MapCol<I, F>
+where
+    I: RowStream,
+{
+    fn build(iter: I, colname: &str, f: F) -> Result<MapCol<I, F>, BuildError> {
+        let headers = iter.headers().clone();
+        let index = match headers.index(colname) {
+            Some(index) => index,
+            None => return Err(BuildError::ColumnNotFound(colname.to_string())),
+        };
+
+        Ok(MapCol {
+            iter,
+            f,
+            index,
+            headers,
+        })
+    }
+}
+
+pub struct IntoIter<I, F> {
+    iter: I,
+    f: F,
+    index: usize,
+    headers: Headers,
+}
+
+impl<I, F> Iterator for IntoIter<I, F>
+where
+    I: Iterator<Item = RowResult>,
+    F: ColMapper,
+{
+    type Item = RowResult;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|result| {
+            result.and_then(|val| {
+                let field = match val.get(self.index) {
+                    Some(field) => field,
+                    None => return Err(Error::ColumnNotFound(
+                        self.headers.as_row().get(self.index).unwrap_or("").to_string()
+                    )),
+                };
+                let new_value = match self.f.map(field, &self.headers, &val) {
+                    Ok(s) => s,
+                    Err(e) => return Err(Error::ColBuildError(e)),
+                };
+                let mut new_row = Row::with_capacity(val.as_slice().len(), val.len());
+
+                for (i, col) in val.iter().enumerate() {
+                    if i == self.index {
+                        new_row.push_field(&new_value);
+                    } else {
+                        new_row.push_field(col);
+                    }
+                }
+
+                Ok(new_row)
+            })
+        })
+    }
+}
+
+impl<I, F> IntoIterator for MapCol<I, F>
+where
+    I: RowStream,
+    F: ColMapper,
+{
+    type Item = RowResult;
+
+    type IntoIter = IntoIter<I::IntoIter, F>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Self::IntoIter {
+            iter: self.iter.into_iter(),
+            f: self.f,
+            index: self.index,
+            headers: self.headers,
+        }
+    }
+}
+
+impl<I, F> RowStream for MapCol<I, F>
+where
+    I: RowStream,
+    F: ColMapper,
+{
+    fn headers(&self) -> &Headers {
+        &self.headers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BuildError, MapCol, Headers, Row, RowStream};
+    use crate::{ColSpec, col, mock::MockStream, error::Error};
+
+    #[test]
+    fn test_map_col() {
+        let iter = MockStream::from_rows(
+            vec![
+                Ok(Row::from(vec!["id", "name"])),
+                Ok(Row::from(vec!["1", " Juan"])),
+                Ok(Row::from(vec!["2", "pedro "])),
+                Err(Error::InconsistentHeaders),
+                Ok(Row::from(vec!["3", ""])),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+
+        let map = MapCol::new(iter, "name", |field| {
+            if field.is_empty() {
+                Err(col::BuildError::Generic("empty name".to_string()))
+            } else {
+                Ok(field.trim().to_lowercase())
+            }
+        }).unwrap();
+
+        assert_eq!(
+            *map.headers(),
+            Headers::from_row(Row::from(vec!["id", "name"])),
+        );
+
+        let mut map = map.into_iter();
+
+        assert_eq!(
+            map.next().unwrap().unwrap(),
+            Row::from(vec!["1", "juan"])
+        );
+        assert_eq!(
+            map.next().unwrap().unwrap(),
+            Row::from(vec!["2", "pedro"])
+        );
+
+        match map.next() {
+            Some(Err(Error::InconsistentHeaders)) => {},
+            _ => unreachable!(),
+        }
+
+        match map.next() {
+            Some(Err(Error::ColBuildError(col::BuildError::Generic(_)))) => {},
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_map_col_spec() {
+        let iter = MockStream::from_rows(
+            vec![
+                Ok(Row::from(vec!["day", "month", "date"])),
+                Ok(Row::from(vec!["09", "02", ""])),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+
+        let mut map = MapCol::from_spec(iter, ColSpec::Mix {
+            colname: "date".to_string(),
+            coldef: "{day}/{month}".to_string(),
+        }).unwrap().into_iter();
+
+        assert_eq!(
+            map.next().unwrap().unwrap(),
+            Row::from(vec!["09", "02", "09/02"])
+        );
+    }
+
+    #[test]
+    fn test_map_col_unexistent_column() {
+        let iter = MockStream::from_rows(
+            vec![
+                Ok(Row::from(vec!["id"])),
+                Ok(Row::from(vec!["1"])),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+
+        match MapCol::new(iter, "foo", |field| Ok(field.to_string())) {
+            Err(BuildError::ColumnNotFound(col)) => assert_eq!(col, "foo"),
+            _ => unreachable!(),
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use crate::{Headers, RowResult, RowStream, Error};
+use std::collections::HashMap;
 use std::rc::Rc;
 use std::vec;
 
@@ -12,54 +13,87 @@ use group::Group;
 #[derive(Debug)]
 pub enum BuildError {
     DuplicatedHeader(String),
+    GroupingKeyError(String),
 }
 
 /// Used to group and aggregate the rows, yielding the results as a new stream
 /// of rows with potentially new columns.
-pub struct Reduce<I> {
+///
+/// One row is emitted for every distinct combination of values of the
+/// grouping columns, in the order each combination was first seen. If no
+/// grouping columns are given the whole stream is reduced to a single row.
+///
+/// The aggregates are given by a closure that is called once for every group
+/// found, so that each group starts with fresh aggregates.
+pub struct Reduce<I, F> {
     iter: I,
-    columns: Vec<Box<dyn Aggregate>>,
+    group_by: Vec<String>,
+    columns: F,
     headers: Headers,
+    /// Number of columns of the stream, which come before the aggregates
+    stream_columns: usize,
 }
 
-impl<I> Reduce<I>
+impl<I, F> Reduce<I, F>
 where
     I: RowStream,
+    F: Fn() -> Vec<Box<dyn Aggregate>>,
 {
     /// Creates a new Reduce from an implementor of the RowStream trait, a set
-    /// of column names for grouping and a set of aggregates to calculate and
-    /// add as columns.
+    /// of column names for grouping and a closure building the aggregates to
+    /// calculate and add as columns.
     pub fn new(
         iter: I,
-        columns: Vec<Box<dyn Aggregate>>,
-    ) -> Result<Reduce<I>, BuildError> {
+        grouping: &[&str],
+        columns: F,
+    ) -> Result<Reduce<I, F>, BuildError> {
         let mut headers = iter.headers().clone();
-        let mut whole_columns: Vec<Box<dyn Aggregate>> = Vec::with_capacity(headers.len() + columns.len());
+        let stream_columns = headers.len();
+        let mut group_by = Vec::with_capacity(grouping.len());
 
-        for header in headers.iter() {
-            let source = Rc::new(header.to_string());
+        for key in grouping.iter() {
+            if !headers.contains_key(key) {
+                return Err(BuildError::GroupingKeyError(key.to_string()));
+            }
 
-            whole_columns.push(Box::new(aggregate::Last::new(header, &source)));
+            group_by.push(key.to_string());
         }
 
-        for col in columns.iter() {
+        for col in (columns)().iter() {
             if let Err(_) = headers.add(col.colname()) {
                 return Err(BuildError::DuplicatedHeader(col.colname().to_string()));
             }
         }
 
-        for column in columns {
-            whole_columns.push(column);
-        }
-
         Ok(Reduce {
             iter,
-            columns: whole_columns,
+            group_by,
+            columns,
             headers,
+            stream_columns,
         })
     }
 }
 
+/// A group with fresh aggregates: the last value of every column of the
+/// stream followed by the ones built by `columns`.
+fn new_group<F>(headers: &Headers, stream_columns: usize, columns: &F) -> Group
+where
+    F: Fn() -> Vec<Box<dyn Aggregate>>,
+{
+    let mut whole_columns: Vec<Box<dyn Aggregate>> = Vec::with_capacity(headers.len());
+
+    for header in headers.iter().take(stream_columns) {
+        let source = Rc::new(header.to_string());
+
+        whole_columns.push(Box::new(aggregate::Last::new(header, &source)));
+    }
+
+    whole_columns.extend((columns)());
+
+    Group::from(whole_columns)
+}
+
 pub struct IntoIter {
     iter: vec::IntoIter<RowResult>,
 }
@@ -73,29 +107,54 @@ impl Iterator for IntoIter
     }
 }
 
-impl<I> IntoIterator for Reduce<I>
+impl<I, F> IntoIterator for Reduce<I, F>
 where
     I: RowStream,
+    F: Fn() -> Vec<Box<dyn Aggregate>>,
 {
     type Item = RowResult;
 
     type IntoIter = IntoIter;
 
     fn into_iter(self) -> Self::IntoIter {
+        let Reduce { iter, group_by, columns, headers, stream_columns } = self;
         let mut errors = vec![];
-        let mut onlygroup = Group::from(self.columns);
+        // groups in the order they were first seen, and where to find each
+        let mut groups: Vec<Group> = Vec::new();
+        let mut indexes: HashMap<Vec<String>, usize> = HashMap::new();
+
+        if group_by.is_empty() {
+            indexes.insert(Vec::new(), 0);
+            groups.push(new_group(&headers, stream_columns, &columns));
+        }
 
-        for item in self.iter {
+        for item in iter {
             match item {
-                Ok(row) => match onlygroup.update(&self.headers, &row) {
-                    Err(e) => errors.push(Err(Error::AggregateError(e))),
-                    _ => {},
+                Ok(row) => {
+                    let key = match headers.key(&row, &group_by) {
+                        Ok(key) => key,
+                        Err(col) => {
+                            errors.push(Err(Error::ColumnNotFound(col)));
+                            continue;
+                        }
+                    };
+                    let index = *indexes.entry(key).or_insert_with(|| {
+                        groups.push(new_group(&headers, stream_columns, &columns));
+
+                        groups.len() - 1
+                    });
+
+                    if let Err(e) = groups[index].update(&headers, &row) {
+                        errors.push(Err(Error::AggregateError(e)));
+                    }
                 },
                 Err(e) => errors.push(Err(e)),
             }
         }
 
-        errors.push(Ok(onlygroup.as_row()));
+        for group in groups {
+            errors.push(Ok(group.as_row()));
+        }
 
         IntoIter {
             iter: errors.into_iter(),
@@ -103,9 +162,9 @@ where
     }
 }
 
-impl<I> RowStream for Reduce<I>
+impl<I, F> RowStream for Reduce<I, F>
 where
-    Reduce<I>: IntoIterator<Item = RowResult>,
+    Reduce<I, F>: IntoIterator<Item = RowResult>,
 {
     fn headers(&self) -> &Headers {
         &self.headers
@@ -114,8 +173,8 @@ where
 
 #[cfg(test)]
 mod tests {
-    use super::{Reduce, aggregate::{Avg, Sum, Max, Min}};
-    use crate::{Row, Error, col, mock::MockStream};
+    use super::{Reduce, BuildError, aggregate::{Aggregate, AggregateError, Avg, Count, Sum, Max, Min}};
+    use crate::{Headers, Row, Error, col, mock::MockStream};
 
     #[test]
     fn test_reduce_id_function() {
@@ -130,7 +189,7 @@ mod tests {
         )
         .unwrap();
 
-        let re = Reduce::new(iter, Vec::new()).unwrap();
+        let re = Reduce::new(iter, &[], Vec::new).unwrap();
         let r = re.into_iter();
 
         let mut results: Vec<Row> = r.map(|i| i.unwrap()).collect();
@@ -159,7 +218,7 @@ mod tests {
         )
         .unwrap();
 
-        let r = Reduce::new(iter, vec![Box::new(Avg::new("new", "b"))])
+        let r = Reduce::new(iter, &[], || vec![Box::new(Avg::new("new", "b"))])
             .unwrap()
             .into_iter();
 
@@ -189,7 +248,7 @@ mod tests {
         )
         .unwrap();
 
-        let r = Reduce::new(iter, vec![Box::new(Min::new("new", "b"))])
+        let r = Reduce::new(iter, &[], || vec![Box::new(Min::new("new", "b"))])
             .unwrap()
             .into_iter();
 
@@ -219,7 +278,7 @@ mod tests {
         )
         .unwrap();
 
-        let r = Reduce::new(iter, vec![Box::new(Max::new("new", "b"))])
+        let r = Reduce::new(iter, &[], || vec![Box::new(Max::new("new", "b"))])
             .unwrap()
             .into_iter();
 
@@ -249,7 +308,7 @@ mod tests {
         )
         .unwrap();
 
-        let r = Reduce::new(iter, vec![Box::new(Sum::new("new", "b"))])
+        let r = Reduce::new(iter, &[], || vec![Box::new(Sum::new("new", "b"))])
             .unwrap()
             .into_iter();
 
@@ -265,6 +324,101 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_reduce_grouping() {
+        let iter = MockStream::from_rows(
+            vec![
+                Ok(Row::from(vec!["a", "b"])),
+                Ok(Row::from(vec!["1", "2"])),
+                Ok(Row::from(vec!["2", "7"])),
+                Ok(Row::from(vec!["1", "4"])),
+                Ok(Row::from(vec!["2", "9"])),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+
+        let r = Reduce::new(iter, &["a"], || vec![Box::new(Sum::new("new", "b"))])
+            .unwrap()
+            .into_iter();
+
+        let mut results: Vec<Row> = r.map(|i| i.unwrap()).collect();
+
+        results.sort_by(|a, b| a.as_slice().cmp(b.as_slice()));
+
+        assert_eq!(
+            results,
+            vec![
+                Row::from(vec!["1", "4", "6"]),
+                Row::from(vec!["2", "9", "16"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reduce_first_seen_order() {
+        /// Counts rows, but it is not `Clone`
+        #[derive(Debug)]
+        struct Rows(usize);
+
+        impl Aggregate for Rows {
+            fn update(&mut self, _headers: &Headers, _row: &Row) -> Result<(), AggregateError> {
+                self.0 += 1;
+
+                Ok(())
+            }
+
+            fn value(&self) -> String {
+                self.0.to_string()
+            }
+
+            fn colname(&self) -> &str {
+                "rows"
+            }
+        }
+
+        let mut rows = vec![Ok(Row::from(vec!["a"]))];
+
+        for key in ["d", "b", "c", "e", "a", "c", "d"].iter() {
+            rows.push(Ok(Row::from(vec![*key])));
+        }
+
+        let iter = MockStream::from_rows(rows.into_iter()).unwrap();
+        let results: Vec<Row> = Reduce::new(iter, &["a"], || vec![Box::new(Rows(0))])
+            .unwrap()
+            .into_iter()
+            .map(|i| i.unwrap())
+            .collect();
+
+        assert_eq!(
+            results,
+            vec![
+                Row::from(vec!["d", "2"]),
+                Row::from(vec!["b", "1"]),
+                Row::from(vec!["c", "2"]),
+                Row::from(vec!["e", "1"]),
+                Row::from(vec!["a", "1"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reduce_unexistent_grouping_key() {
+        let iter = MockStream::from_rows(
+            vec![
+                Ok(Row::from(vec!["a", "b"])),
+                Ok(Row::from(vec!["1", "2"])),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+
+        match Reduce::new(iter, &["c"], || vec![Box::new(Sum::new("new", "b"))]) {
+            Err(BuildError::GroupingKeyError(key)) => assert_eq!(key, "c"),
+            _ => panic!("didn't expect this"),
+        }
+    }
+
     #[test]
     fn test_reduce_error() {
         let iter = MockStream::from_rows(
@@ -280,7 +434,7 @@ mod tests {
         )
         .unwrap();
 
-        let mut r = Reduce::new(iter, vec![Box::new(Sum::new("new", "b"))])
+        let mut r = Reduce::new(iter, &[], || vec![Box::new(Sum::new("new", "b"))])
             .unwrap()
             .into_iter();
 
@@ -300,4 +454,45 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn test_reduce_empty_input_without_grouping() {
+        let iter = MockStream::from_rows(
+            vec![
+                Ok(Row::from(vec!["a", "b"])),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+
+        let r = Reduce::new(iter, &[], || vec![Box::new(Count::new("count"))])
+            .unwrap()
+            .into_iter();
+
+        let results: Vec<Row> = r.map(|i| i.unwrap()).collect();
+
+        assert_eq!(
+            results,
+            vec![
+                Row::from(vec!["", "", "0"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reduce_empty_input_with_grouping() {
+        let iter = MockStream::from_rows(
+            vec![
+                Ok(Row::from(vec!["a", "b"])),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+
+        let r = Reduce::new(iter, &["a"], || vec![Box::new(Count::new("count"))])
+            .unwrap()
+            .into_iter();
+
+        assert_eq!(r.count(), 0);
+    }
 }
@@ -4,10 +4,11 @@ use std::{
 };
 
 use crate::{
-    add, col, add_with, error, reduce,
+    add, col, add_with, error, filter, join, map_col, reduce, select, sort,
 
-    Add, ColSpec, Flush, Headers, Inspect, Reduce, Row, RowResult, AddWith,
-    Del, Group, AdjacentGroup, MockStream, Rename, GroupBuildError,
+    Add, ColSpec, Filter, FilterSpec, Flush, Headers, Inspect, MapCol, Reduce, Row, RowResult, AddWith,
+    Del, Group, AdjacentGroup, MockStream, Rename, Select, ColSel, GroupBuildError,
+    GroupKey, WithKey, Sort, SortKey, Join, JoinKind,
 
     aggregate::Aggregate,
     flush::FlushTarget,
@@ -41,6 +42,18 @@ pub trait RowStream: IntoIterator<Item = RowResult> {
         Del::new(self, columns)
     }
 
+    /// Keeps only the given columns, in the given order. Columns can be
+    /// given by name or as [ColSel](enum.ColSel.html) values, using
+    /// `ColSel::Rest` once to keep the columns that are not listed at that
+    /// position. Fails if a column does not exist or is listed twice.
+    fn select<C>(self, columns: &[C]) -> Result<Select<Self>, select::BuildError>
+    where
+        Self: Sized,
+        C: Clone + Into<ColSel>,
+    {
+        Select::new(self, columns)
+    }
+
     /// Adds a column to each row of the stream using a closure to compute its
     /// value
     fn add_with<F>(self, colname: &str, f: F) -> Result<AddWith<Self, F>, add_with::BuildError>
@@ -51,16 +64,79 @@ pub trait RowStream: IntoIterator<Item = RowResult> {
         AddWith::new(self, colname, f)
     }
 
+    /// Rewrites the given column of each row in place using a closure that
+    /// receives the current value of the field. Headers are left untouched.
+    /// Fails if the column does not exist.
+    fn map_col<F>(self, colname: &str, f: F) -> Result<MapCol<Self, F>, map_col::BuildError>
+    where
+        Self: Sized,
+        F: FnMut(&str) -> Result<String, col::BuildError>,
+    {
+        MapCol::new(self, colname, f)
+    }
+
+    /// Like `map_col` but the new value is computed by a `ColSpec`. The
+    /// column rewritten is the one named by the spec's `colname`.
+    fn map_col_by(self, spec: ColSpec) -> Result<MapCol<Self, ColSpec>, map_col::BuildError>
+    where
+        Self: Sized,
+    {
+        MapCol::from_spec(self, spec)
+    }
+
+    /// Keeps only the rows for which the closure returns `true`. Errors are
+    /// passed through untouched.
+    fn filter_rows<F>(self, f: F) -> Filter<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(&Headers, &Row) -> bool,
+    {
+        Filter::new(self, f)
+    }
+
+    /// Like `filter_rows` but using a declarative spec instead of a closure. Fails
+    /// if the column used by the spec does not exist.
+    fn filter_by(self, spec: FilterSpec) -> Result<Filter<Self, FilterSpec>, filter::BuildError>
+    where
+        Self: Sized,
+    {
+        Filter::from_spec(self, spec)
+    }
+
+    /// Joins this stream with another one by the values of the `on` columns,
+    /// that must exist in both. See [Join](struct.Join.html) for details.
+    fn join<R>(self, other: R, on: &[&str], kind: JoinKind) -> Result<Join<Self, R>, join::BuildError>
+    where
+        Self: Sized,
+        R: RowStream,
+    {
+        Join::new(self, other, on, kind)
+    }
+
+    /// Sorts the stream by the given keys, the first one taking precedence.
+    /// Errors are emitted before the sorted rows. Fails if a key's column
+    /// does not exist.
+    fn sort_by(self, keys: Vec<SortKey>) -> Result<Sort<Self>, sort::BuildError>
+    where
+        Self: Sized,
+    {
+        Sort::new(self, keys)
+    }
+
     /// Group by one or more columns, compute aggregates and output the
-    /// resulting columns
-    fn reduce(
+    /// resulting columns, one row per group. With no grouping columns the
+    /// whole stream is reduced to a single row. The aggregates are built by
+    /// `columns` for every group, see [Reduce](struct.Reduce.html).
+    fn reduce<F>(
         self,
-        columns: Vec<Box<dyn Aggregate>>,
-    ) -> Result<Reduce<Self>, reduce::BuildError>
+        grouping: &[&str],
+        columns: F,
+    ) -> Result<Reduce<Self, F>, reduce::BuildError>
     where
         Self: Sized,
+        F: Fn() -> Vec<Box<dyn Aggregate>>,
     {
-        Reduce::new(self, columns)
+        Reduce::new(self, grouping, columns)
     }
 
     fn adjacent_group<H, F, R>(
@@ -78,6 +154,23 @@ pub trait RowStream: IntoIterator<Item = RowResult> {
         AdjacentGroup::new(self, header_map, f, grouping)
     }
 
+    /// Like `adjacent_group` but the closure also receives the key of each
+    /// group
+    fn adjacent_group_with_key<H, F, R>(
+        self,
+        header_map: H,
+        f: F,
+        grouping: &[&str],
+    ) -> Result<AdjacentGroup<Self, WithKey<F>>, GroupBuildError>
+    where
+        H: FnMut(Headers) -> Headers,
+        F: FnMut(&GroupKey, MockStream<vec::IntoIter<RowResult>>) -> R,
+        R: RowStream,
+        Self: Sized,
+    {
+        AdjacentGroup::new_with_key(self, header_map, f, grouping)
+    }
+
     fn group<H, F, R>(
         self,
         header_map: H,
@@ -93,6 +186,22 @@ pub trait RowStream: IntoIterator<Item = RowResult> {
         Group::new(self, header_map, f, grouping)
     }
 
+    /// Like `group` but the closure also receives the key of each group
+    fn group_with_key<H, F, R>(
+        self,
+        header_map: H,
+        f: F,
+        grouping: &[&str],
+    ) -> Result<Group<Self, WithKey<F>>, GroupBuildError>
+    where
+        H: FnMut(Headers) -> Headers,
+        F: FnMut(&GroupKey, MockStream<vec::IntoIter<RowResult>>) -> R,
+        R: RowStream,
+        Self: Sized,
+    {
+        Group::new_with_key(self, header_map, f, grouping)
+    }
+
     /// When consumed, writes to destination specified by the column given in
     /// the first argument. Other than that this behaves like an `id(x)`
     /// function so you can specify more links in the chain and even more
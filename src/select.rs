@@ -0,0 +1,254 @@
+//! Utilities for choosing and reordering columns
+use crate::{
+    Headers, Row, RowStream,
+    error::{Error, RowResult},
+};
+
+/// One of the columns given to [Select](struct.Select.html).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ColSel {
+    /// The column with this name.
+    Col(String),
+
+    /// Every column that is not explicitly listed, in their original order.
+    /// It can be given only once.
+    Rest,
+}
+
+impl From<&str> for ColSel {
+    fn from(name: &str) -> ColSel {
+        ColSel::Col(name.to_string())
+    }
+}
+
+#[derive(Debug)]
+pub enum BuildError {
+    ColumnNotFound(String),
+    DuplicatedColumn(String),
+    DuplicatedRest,
+}
+
+/// Keeps only the specified columns of each row, in the order they were
+/// given.
+pub struct Select<I> {
+    iter: I,
+    indexes: Vec<usize>,
+    headers: Headers,
+}
+
+impl<I> Select<I>
+where
+    I: RowStream,
+{
+    pub fn new<C>(iter: I, columns: &[C]) -> Result<Select<I>, BuildError>
+    where
+        C: Clone + Into<ColSel>,
+    {
+        let old_headers = iter.headers();
+        let columns: Vec<ColSel> = columns.iter().cloned().map(Into::into).collect();
+        let mut listed = Vec::with_capacity(columns.len());
+        let mut has_rest = false;
+
+        for col in columns.iter() {
+            match col {
+                ColSel::Rest if has_rest => return Err(BuildError::DuplicatedRest),
+                ColSel::Rest => has_rest = true,
+                ColSel::Col(name) => match old_headers.index(name) {
+                    Some(index) if listed.contains(&index) => {
+                        return Err(BuildError::DuplicatedColumn(name.to_string()));
+                    },
+                    Some(index) => listed.push(index),
+                    None => return Err(BuildError::ColumnNotFound(name.to_string())),
+                },
+            }
+        }
+
+        let mut indexes = Vec::with_capacity(old_headers.len());
+
+        for col in columns.iter() {
+            match col {
+                ColSel::Rest => indexes.extend(
+                    (0..old_headers.len()).filter(|i| !listed.contains(i))
+                ),
+                ColSel::Col(name) => indexes.extend(old_headers.index(name)),
+            }
+        }
+
+        let mut header_row = Row::with_capacity(0, indexes.len());
+
+        for index in indexes.iter() {
+            header_row.push_field(old_headers.as_row().get(*index).unwrap());
+        }
+
+        Ok(Select {
+            headers: Headers::from_row(header_row),
+            iter,
+            indexes,
+        })
+    }
+}
+
+pub struct IntoIter<I> {
+    iter: I,
+    indexes: Vec<usize>,
+    headers: Headers,
+}
+
+impl<I> Iterator for IntoIter<I>
+where
+    I: Iterator<Item = RowResult>,
+{
+    type Item = RowResult;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|result| {
+            result.and_then(|val| {
+                let mut new_row = Row::with_capacity(val.as_slice().len(), self.indexes.len());
+
+                for (header, index) in self.headers.iter().zip(self.indexes.iter()) {
+                    match val.get(*index) {
+                        Some(field) => new_row.push_field(field),
+                        None => return Err(Error::ColumnNotFound(header.to_string())),
+                    }
+                }
+
+                Ok(new_row)
+            })
+        })
+    }
+}
+
+impl<I> IntoIterator for Select<I>
+where
+    I: RowStream,
+{
+    type Item = RowResult;
+
+    type IntoIter = IntoIter<I::IntoIter>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Self::IntoIter {
+            iter: self.iter.into_iter(),
+            indexes: self.indexes,
+            headers: self.headers,
+        }
+    }
+}
+
+impl<I> RowStream for Select<I>
+where
+    I: RowStream,
+{
+    fn headers(&self) -> &Headers {
+        &self.headers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BuildError, ColSel, Select, Headers, Row, RowStream};
+    use crate::mock::MockStream;
+
+    #[test]
+    fn test_select() {
+        let iter = MockStream::from_rows(
+            vec![
+                Ok(Row::from(vec!["a", "b", "c"])),
+                Ok(Row::from(vec!["1", "2", "3"])),
+                Ok(Row::from(vec!["4", "5", "6"])),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+
+        let select = Select::new(iter, &["c", "a"]).unwrap();
+
+        assert_eq!(
+            *select.headers(),
+            Headers::from_row(Row::from(vec!["c", "a"])),
+        );
+
+        let mut select = select.into_iter();
+
+        assert_eq!(
+            select.next().unwrap().unwrap(),
+            Row::from(vec!["3", "1"])
+        );
+        assert_eq!(
+            select.next().unwrap().unwrap(),
+            Row::from(vec!["6", "4"])
+        );
+    }
+
+    #[test]
+    fn test_select_rest() {
+        let iter = MockStream::from_rows(
+            vec![
+                Ok(Row::from(vec!["a", "b", "c", "d"])),
+                Ok(Row::from(vec!["1", "2", "3", "4"])),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+
+        let select = Select::new(iter, &[ColSel::from("c"), ColSel::Rest, ColSel::from("a")]).unwrap();
+
+        assert_eq!(
+            *select.headers(),
+            Headers::from_row(Row::from(vec!["c", "b", "d", "a"])),
+        );
+
+        let mut select = select.into_iter();
+
+        assert_eq!(
+            select.next().unwrap().unwrap(),
+            Row::from(vec!["3", "2", "4", "1"])
+        );
+    }
+
+    #[test]
+    fn test_select_errors() {
+        let rows = || MockStream::from_rows(
+            vec![
+                Ok(Row::from(vec!["a", "b"])),
+                Ok(Row::from(vec!["1", "2"])),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+
+        match Select::new(rows(), &["a", "foo"]) {
+            Err(BuildError::ColumnNotFound(col)) => assert_eq!(col, "foo"),
+            _ => unreachable!(),
+        }
+
+        match Select::new(rows(), &["b", "a", "b"]) {
+            Err(BuildError::DuplicatedColumn(col)) => assert_eq!(col, "b"),
+            _ => unreachable!(),
+        }
+
+        match Select::new(rows(), &[ColSel::Rest, ColSel::from("a"), ColSel::Rest]) {
+            Err(BuildError::DuplicatedRest) => {},
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_select_column_named_like_rest() {
+        let iter = MockStream::from_rows(
+            vec![
+                Ok(Row::from(vec!["...", "a"])),
+                Ok(Row::from(vec!["1", "2"])),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+
+        let select = Select::new(iter, &["a", "..."]).unwrap();
+
+        assert_eq!(
+            *select.headers(),
+            Headers::from_row(Row::from(vec!["a", "..."])),
+        );
+    }
+}
@@ -0,0 +1,298 @@
+//! Utilities for sorting a stream by one or more columns
+use std::vec;
+
+use crate::{
+    Headers, Row, RowStream,
+    error::{Error, RowResult},
+};
+
+pub(crate) mod key;
+pub(crate) mod runs;
+
+pub use key::{SortKey, SortKind, SortOrder};
+
+use key::{sort_values, SortValue};
+use runs::{Merge, Runs};
+
+#[derive(Debug)]
+pub enum BuildError {
+    ColumnNotFound(String),
+}
+
+/// Sorts the rows of a stream by one or more [SortKey](struct.SortKey.html)s,
+/// the first one taking precedence. The sort is stable, so rows with equal
+/// keys keep their relative order.
+///
+/// Errors found in the stream are emitted first, followed by the sorted
+/// rows.
+///
+/// Sorting needs to read the whole stream, which by default is kept in
+/// memory. For big inputs a limit can be set with
+/// [with_memory_budget](#method.with_memory_budget), past which sorted runs
+/// are written to temporary files and merged at the end.
+pub struct Sort<I> {
+    iter: I,
+    keys: Vec<SortKey>,
+    headers: Headers,
+    memory_budget: Option<usize>,
+}
+
+impl<I> Sort<I>
+where
+    I: RowStream,
+{
+    pub fn new(iter: I, keys: Vec<SortKey>) -> Result<Sort<I>, BuildError> {
+        let headers = iter.headers().clone();
+
+        for key in keys.iter() {
+            if !headers.contains_key(&key.colname) {
+                return Err(BuildError::ColumnNotFound(key.colname.clone()));
+            }
+        }
+
+        Ok(Sort {
+            iter,
+            keys,
+            headers,
+            memory_budget: None,
+        })
+    }
+
+    /// Limits the amount of row data, in bytes, kept in memory. Every time it
+    /// is exceeded the rows read so far are sorted and written to a temporary
+    /// file, removed once the stream is consumed.
+    pub fn with_memory_budget(self, bytes: usize) -> Sort<I> {
+        Sort {
+            memory_budget: Some(bytes),
+            ..self
+        }
+    }
+}
+
+fn sort_buffer(buffer: &mut [(Vec<SortValue>, Row)]) {
+    buffer.sort_by(|a, b| a.0.cmp(&b.0));
+}
+
+fn write_run(runs: &mut Option<Runs>, buffer: &mut Vec<(Vec<SortValue>, Row)>) -> csv::Result<()> {
+    sort_buffer(buffer);
+
+    if runs.is_none() {
+        *runs = Some(Runs::new()?);
+    }
+
+    runs.as_mut().unwrap().write(buffer.iter().map(|(_, row)| row))?;
+    buffer.clear();
+
+    Ok(())
+}
+
+enum Sorted {
+    Memory(vec::IntoIter<(Vec<SortValue>, Row)>),
+    Disk(Box<Merge>),
+}
+
+pub struct IntoIter {
+    errors: vec::IntoIter<RowResult>,
+    sorted: Sorted,
+}
+
+impl Iterator for IntoIter {
+    type Item = RowResult;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(error) = self.errors.next() {
+            return Some(error);
+        }
+
+        match self.sorted {
+            Sorted::Memory(ref mut rows) => rows.next().map(|(_, row)| Ok(row)),
+            Sorted::Disk(ref mut merge) => merge.next().map(|result| result.map_err(Error::Csv)),
+        }
+    }
+}
+
+impl<I> IntoIterator for Sort<I>
+where
+    I: RowStream,
+{
+    type Item = RowResult;
+
+    type IntoIter = IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let mut errors = Vec::new();
+        let mut buffer = Vec::new();
+        let mut runs: Option<Runs> = None;
+        let mut memory_budget = self.memory_budget;
+        let mut buffered = 0;
+
+        for result in self.iter {
+            match result {
+                Ok(row) => {
+                    buffered += row.as_slice().len();
+                    buffer.push((sort_values(&self.keys, &self.headers, &row), row));
+
+                    if memory_budget.map(|budget| buffered > budget).unwrap_or(false) {
+                        match write_run(&mut runs, &mut buffer) {
+                            Ok(()) => buffered = 0,
+                            Err(e) => {
+                                // keep working in memory
+                                memory_budget = None;
+                                errors.push(Err(Error::Csv(e)));
+                            },
+                        }
+                    }
+                },
+                Err(error) => errors.push(Err(error)),
+            }
+        }
+
+        sort_buffer(&mut buffer);
+
+        let sorted = match runs {
+            Some(runs) => match runs.merge(buffer, self.keys, self.headers) {
+                Ok(merge) => Sorted::Disk(Box::new(merge)),
+                Err(e) => {
+                    errors.push(Err(Error::Csv(e)));
+
+                    Sorted::Memory(Vec::new().into_iter())
+                },
+            },
+            None => Sorted::Memory(buffer.into_iter()),
+        };
+
+        IntoIter {
+            errors: errors.into_iter(),
+            sorted,
+        }
+    }
+}
+
+impl<I> RowStream for Sort<I>
+where
+    I: RowStream,
+{
+    fn headers(&self) -> &Headers {
+        &self.headers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BuildError, Sort, SortKey, SortKind, Headers, Row, RowStream};
+    use crate::{mock::MockStream, error::Error};
+
+    fn stream() -> MockStream<std::vec::IntoIter<Result<Row, Error>>> {
+        MockStream::from_rows(
+            vec![
+                Ok(Row::from(vec!["name", "age", "date"])),
+                Ok(Row::from(vec!["juan", "9", "09/02/2019"])),
+                Ok(Row::from(vec!["ana", "10", "01/03/2018"])),
+                Err(Error::InconsistentHeaders),
+                Ok(Row::from(vec!["pedro", "9", "15/01/2019"])),
+                Ok(Row::from(vec!["ana", "7", "chicken"])),
+            ]
+            .into_iter(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_sort() {
+        let sort = Sort::new(stream(), vec![
+            SortKey::asc("name", SortKind::Str),
+            SortKey::desc("age", SortKind::Num),
+        ]).unwrap();
+
+        assert_eq!(
+            *sort.headers(),
+            Headers::from_row(Row::from(vec!["name", "age", "date"])),
+        );
+
+        let mut sort = sort.into_iter();
+
+        match sort.next() {
+            Some(Err(Error::InconsistentHeaders)) => {},
+            _ => unreachable!(),
+        }
+
+        let results: Vec<Row> = sort.map(|r| r.unwrap()).collect();
+
+        assert_eq!(
+            results,
+            vec![
+                Row::from(vec!["ana", "10", "01/03/2018"]),
+                Row::from(vec!["ana", "7", "chicken"]),
+                Row::from(vec!["juan", "9", "09/02/2019"]),
+                Row::from(vec!["pedro", "9", "15/01/2019"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sort_numeric_is_stable() {
+        let sort = Sort::new(stream(), vec![
+            SortKey::asc("age", SortKind::Num),
+        ]).unwrap();
+
+        let results: Vec<Row> = sort.into_iter().filter_map(|r| r.ok()).collect();
+
+        assert_eq!(
+            results,
+            vec![
+                Row::from(vec!["ana", "7", "chicken"]),
+                Row::from(vec!["juan", "9", "09/02/2019"]),
+                Row::from(vec!["pedro", "9", "15/01/2019"]),
+                Row::from(vec!["ana", "10", "01/03/2018"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sort_date() {
+        let sort = Sort::new(stream(), vec![
+            SortKey::asc("date", SortKind::Date("%d/%m/%Y".to_string())),
+        ]).unwrap();
+
+        let results: Vec<Row> = sort.into_iter().filter_map(|r| r.ok()).collect();
+
+        assert_eq!(
+            results,
+            vec![
+                Row::from(vec!["ana", "10", "01/03/2018"]),
+                Row::from(vec!["pedro", "9", "15/01/2019"]),
+                Row::from(vec!["juan", "9", "09/02/2019"]),
+                Row::from(vec!["ana", "7", "chicken"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sort_spill_to_disk() {
+        let rows: Vec<_> = (0..50)
+            .map(|i| Ok(Row::from(vec![format!("{}", (i * 7) % 10), format!("{}", i)])))
+            .collect();
+        let iter = MockStream::new(rows.into_iter(), Headers::from_row(Row::from(vec!["key", "pos"])));
+
+        let sort = Sort::new(iter, vec![
+            SortKey::asc("key", SortKind::Num),
+        ]).unwrap().with_memory_budget(16);
+
+        let results: Vec<Row> = sort.into_iter().map(|r| r.unwrap()).collect();
+        let mut expected: Vec<Row> = (0..50)
+            .map(|i| Row::from(vec![format!("{}", (i * 7) % 10), format!("{}", i)]))
+            .collect();
+
+        expected.sort_by_key(|row| row.get(0).unwrap().parse::<u32>().unwrap());
+
+        assert_eq!(results, expected);
+    }
+
+    #[test]
+    fn test_sort_unexistent_column() {
+        match Sort::new(stream(), vec![SortKey::asc("foo", SortKind::Str)]) {
+            Err(BuildError::ColumnNotFound(col)) => assert_eq!(col, "foo"),
+            _ => unreachable!(),
+        }
+    }
+}
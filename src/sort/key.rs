@@ -0,0 +1,217 @@
+use std::cmp::Ordering;
+
+use crate::{Headers, Row};
+
+/// Direction of a sort key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+/// How the values of a sort key are compared.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SortKind {
+    /// Compare as strings.
+    Str,
+
+    /// Compare as numbers.
+    Num,
+
+    /// Compare as dates with the given format, made of literal characters and
+    /// the fields `%Y`, `%m`, `%d`, `%H`, `%M` and `%S`, e.g. `%d/%m/%Y`.
+    Date(String),
+}
+
+/// One of the columns used to sort a stream.
+///
+/// Values that cannot be read as the given kind are sorted after every valid
+/// value (before them when the order is descending).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortKey {
+    pub colname: String,
+    pub order: SortOrder,
+    pub kind: SortKind,
+}
+
+impl SortKey {
+    pub fn asc(colname: &str, kind: SortKind) -> SortKey {
+        SortKey {
+            colname: colname.to_string(),
+            order: SortOrder::Asc,
+            kind,
+        }
+    }
+
+    pub fn desc(colname: &str, kind: SortKind) -> SortKey {
+        SortKey {
+            colname: colname.to_string(),
+            order: SortOrder::Desc,
+            kind,
+        }
+    }
+
+    fn value(&self, field: &str) -> SortValue {
+        let value = match self.kind {
+            SortKind::Str => Value::Str(field.to_string()),
+            SortKind::Num => match field.parse::<f64>() {
+                Ok(num) if !num.is_nan() => Value::Num(num),
+                _ => Value::Invalid(field.to_string()),
+            },
+            SortKind::Date(ref format) => match parse_date(field, format) {
+                Some(date) => Value::Date(date),
+                None => Value::Invalid(field.to_string()),
+            },
+        };
+
+        SortValue {
+            value,
+            order: self.order,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Value {
+    Str(String),
+    Num(f64),
+    Date([u32; 6]),
+    Invalid(String),
+}
+
+impl Eq for Value {}
+
+impl Ord for Value {
+    fn cmp(&self, other: &Value) -> Ordering {
+        match (self, other) {
+            (Value::Str(a), Value::Str(b)) => a.cmp(b),
+            (Value::Num(a), Value::Num(b)) => a.partial_cmp(b).unwrap_or(Ordering::Equal),
+            (Value::Date(a), Value::Date(b)) => a.cmp(b),
+            (Value::Invalid(a), Value::Invalid(b)) => a.cmp(b),
+            (Value::Invalid(_), _) => Ordering::Greater,
+            (_, Value::Invalid(_)) => Ordering::Less,
+            // values of the same key are always of the same kind
+            _ => Ordering::Equal,
+        }
+    }
+}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Value) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// The value of one sort key for a given row, already taking its direction
+/// into account.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SortValue {
+    value: Value,
+    order: SortOrder,
+}
+
+impl Ord for SortValue {
+    fn cmp(&self, other: &SortValue) -> Ordering {
+        match self.order {
+            SortOrder::Asc => self.value.cmp(&other.value),
+            SortOrder::Desc => other.value.cmp(&self.value),
+        }
+    }
+}
+
+impl PartialOrd for SortValue {
+    fn partial_cmp(&self, other: &SortValue) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Computes the values of the sort keys for the given row. Rows can be
+/// compared by comparing these values.
+pub fn sort_values(keys: &[SortKey], headers: &Headers, row: &Row) -> Vec<SortValue> {
+    keys.iter()
+        .map(|key| key.value(headers.get_field(row, &key.colname).unwrap_or("")))
+        .collect()
+}
+
+/// Reads a date given its format. Returns its year, month, day, hour, minute
+/// and second, so dates can be compared by comparing the results.
+fn parse_date(value: &str, format: &str) -> Option<[u32; 6]> {
+    let mut parts = [0; 6];
+    let mut chars = value.chars().peekable();
+    let mut fmt = format.chars();
+
+    while let Some(c) = fmt.next() {
+        if c != '%' {
+            if chars.next()? != c {
+                return None;
+            }
+
+            continue;
+        }
+
+        let (index, max_digits) = match fmt.next()? {
+            'Y' => (0, 4),
+            'm' => (1, 2),
+            'd' => (2, 2),
+            'H' => (3, 2),
+            'M' => (4, 2),
+            'S' => (5, 2),
+            '%' => {
+                if chars.next()? != '%' {
+                    return None;
+                }
+
+                continue;
+            },
+            _ => return None,
+        };
+        let mut digits = 0;
+        let mut num = 0;
+
+        while digits < max_digits {
+            match chars.peek().and_then(|c| c.to_digit(10)) {
+                Some(d) => {
+                    num = num * 10 + d;
+                    digits += 1;
+                    chars.next();
+                },
+                None => break,
+            }
+        }
+
+        if digits == 0 {
+            return None;
+        }
+
+        parts[index] = num;
+    }
+
+    match chars.next() {
+        Some(_) => None,
+        None => Some(parts),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_date, SortKey, SortKind};
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(parse_date("09/02/2019", "%d/%m/%Y"), Some([2019, 2, 9, 0, 0, 0]));
+        assert_eq!(parse_date("2019-2-9 13:05", "%Y-%m-%d %H:%M"), Some([2019, 2, 9, 13, 5, 0]));
+        assert_eq!(parse_date("2019-02-09", "%d/%m/%Y"), None);
+        assert_eq!(parse_date("09/02/2019 extra", "%d/%m/%Y"), None);
+    }
+
+    #[test]
+    fn test_sort_value_order() {
+        let asc = SortKey::asc("a", SortKind::Num);
+        let desc = SortKey::desc("a", SortKind::Num);
+
+        assert!(asc.value("2") < asc.value("10"));
+        assert!(asc.value("10") < asc.value("chicken"));
+        assert!(desc.value("10") < desc.value("2"));
+        assert!(desc.value("chicken") < desc.value("2"));
+    }
+}
@@ -0,0 +1,350 @@
+//! Sorted runs stored on disk and their k-way merge
+use csv::{ReaderBuilder, StringRecordsIntoIter, WriterBuilder};
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, VecDeque};
+use std::fs::{self, File};
+use std::io;
+use std::path::PathBuf;
+use std::vec;
+
+use super::key::{sort_values, SortKey, SortValue};
+use crate::{Headers, Row, temp::TempDir};
+
+/// Maximum number of runs merged at once, which bounds the number of files
+/// open at the same time. Having more runs than this takes several passes.
+const MAX_FAN_IN: usize = 64;
+
+/// Already sorted sets of rows written to temporary CSV files.
+pub struct Runs {
+    dir: TempDir,
+    paths: Vec<PathBuf>,
+    /// Number of rows written to each run
+    counts: Vec<usize>,
+}
+
+impl Runs {
+    pub fn new() -> csv::Result<Runs> {
+        Ok(Runs {
+            dir: TempDir::new("csvsc-sort")?,
+            paths: Vec::new(),
+            counts: Vec::new(),
+        })
+    }
+
+    /// Writes a new run. Rows must be already sorted.
+    pub fn write<I, R>(&mut self, rows: I) -> csv::Result<()>
+    where
+        I: IntoIterator<Item = R>,
+        R: Borrow<Row>,
+    {
+        let path = self.dir.path().join(format!("{}.csv", self.paths.len()));
+        let mut writer = WriterBuilder::new().flexible(true).from_path(&path)?;
+        let mut count = 0;
+
+        for row in rows {
+            writer.write_record(row.borrow())?;
+            count += 1;
+        }
+
+        writer.flush()?;
+        self.paths.push(path);
+        self.counts.push(count);
+
+        Ok(())
+    }
+
+    /// Merges all the runs and the given rows, that are still in memory, into
+    /// a single sorted stream of rows. The rows in memory must be already
+    /// sorted and come after those in the runs in the original input.
+    ///
+    /// If there are more than `MAX_FAN_IN` runs, groups of consecutive runs
+    /// are first merged into bigger ones until few enough are left. Rows
+    /// that can't be read in these passes are reported by the final merge.
+    pub fn merge(self, memory: Vec<(Vec<SortValue>, Row)>, keys: Vec<SortKey>, headers: Headers) -> csv::Result<Merge> {
+        let mut paths = self.paths;
+        let mut counts = self.counts;
+        let mut errors = VecDeque::new();
+        let mut pass = 0;
+
+        while paths.len() > MAX_FAN_IN {
+            let mut merged = Vec::with_capacity(paths.len() / MAX_FAN_IN + 1);
+            let mut merged_counts = Vec::with_capacity(paths.len() / MAX_FAN_IN + 1);
+
+            for (i, (chunk, chunk_counts)) in paths.chunks(MAX_FAN_IN).zip(counts.chunks(MAX_FAN_IN)).enumerate() {
+                let path = self.dir.path().join(format!("merge-{}-{}.csv", pass, i));
+                let mut writer = WriterBuilder::new().flexible(true).from_path(&path)?;
+                let mut count = 0;
+
+                for row in Merge::open(chunk, chunk_counts, Vec::new(), keys.clone(), headers.clone(), None)? {
+                    match row {
+                        Ok(row) => {
+                            writer.write_record(&row)?;
+                            count += 1;
+                        },
+                        Err(e) => errors.push_back(e),
+                    }
+                }
+
+                writer.flush()?;
+
+                for old in chunk {
+                    let _ = fs::remove_file(old);
+                }
+
+                merged.push(path);
+                merged_counts.push(count);
+            }
+
+            paths = merged;
+            counts = merged_counts;
+            pass += 1;
+        }
+
+        let mut merge = Merge::open(&paths, &counts, memory, keys, headers, Some(self.dir))?;
+
+        errors.append(&mut merge.errors);
+        merge.errors = errors;
+
+        Ok(merge)
+    }
+}
+
+/// The head of one of the runs while merging.
+struct Head {
+    values: Vec<SortValue>,
+    run: usize,
+    row: Row,
+}
+
+impl PartialEq for Head {
+    fn eq(&self, other: &Head) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Head {}
+
+impl Ord for Head {
+    // reversed because BinaryHeap is a max-heap. Ties are broken by run so
+    // rows with equal keys keep the order they had in the input.
+    fn cmp(&self, other: &Head) -> Ordering {
+        other.values.cmp(&self.values).then_with(|| other.run.cmp(&self.run))
+    }
+}
+
+impl PartialOrd for Head {
+    fn partial_cmp(&self, other: &Head) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Yields the rows of several sorted runs in order.
+///
+/// Every row of a run that can't be read yields an error in its place, after
+/// the row that preceded it, and the run is read again before going on. Runs
+/// know how many rows they hold, so a run that keeps failing or ends too soon
+/// yields exactly one error for each of its missing rows.
+pub struct Merge {
+    readers: Vec<StringRecordsIntoIter<File>>,
+    /// Rows of each run not read yet
+    remaining: Vec<usize>,
+    memory: vec::IntoIter<(Vec<SortValue>, Row)>,
+    heap: BinaryHeap<Head>,
+    keys: Vec<SortKey>,
+    headers: Headers,
+    errors: VecDeque<csv::Error>,
+    /// Runs whose last read failed, to be read again
+    failed: Vec<usize>,
+    _dir: Option<TempDir>,
+}
+
+impl Merge {
+    fn open(
+        paths: &[PathBuf],
+        counts: &[usize],
+        memory: Vec<(Vec<SortValue>, Row)>,
+        keys: Vec<SortKey>,
+        headers: Headers,
+        dir: Option<TempDir>,
+    ) -> csv::Result<Merge> {
+        let mut merge = Merge {
+            readers: Vec::with_capacity(paths.len()),
+            remaining: counts.to_vec(),
+            memory: memory.into_iter(),
+            heap: BinaryHeap::with_capacity(paths.len() + 1),
+            keys,
+            headers,
+            errors: VecDeque::new(),
+            failed: Vec::new(),
+            _dir: dir,
+        };
+
+        for path in paths {
+            merge.readers.push(
+                ReaderBuilder::new()
+                    .has_headers(false)
+                    .flexible(true)
+                    .from_path(path)?
+                    .into_records()
+            );
+        }
+
+        for run in 0..=merge.readers.len() {
+            merge.advance(run);
+        }
+
+        Ok(merge)
+    }
+
+    /// Reads the next row of the given run into the heap. The run after the
+    /// last one on disk is the one kept in memory. A failed read counts as
+    /// one of the rows of the run.
+    fn advance(&mut self, run: usize) {
+        if run == self.readers.len() {
+            if let Some((values, row)) = self.memory.next() {
+                self.heap.push(Head { values, run, row });
+            }
+
+            return;
+        }
+
+        if self.remaining[run] == 0 {
+            return;
+        }
+
+        self.remaining[run] -= 1;
+
+        match self.readers[run].next() {
+            Some(Ok(row)) => self.heap.push(Head {
+                values: sort_values(&self.keys, &self.headers, &row),
+                run,
+                row,
+            }),
+            Some(Err(e)) => {
+                self.errors.push_back(e);
+                self.failed.push(run);
+            },
+            None => {
+                self.errors.push_back(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "a sorted run ended before all its rows were read",
+                ).into());
+                self.failed.push(run);
+            },
+        }
+    }
+}
+
+impl Iterator for Merge {
+    type Item = csv::Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            // an error reading a run is reported after the row that preceded it
+            if let Some(e) = self.errors.pop_front() {
+                return Some(Err(e));
+            }
+
+            // runs that failed are read again before taking any other row, so
+            // their rows keep their place
+            match self.failed.pop() {
+                Some(run) => self.advance(run),
+                None => break,
+            }
+        }
+
+        let head = self.heap.pop()?;
+
+        self.advance(head.run);
+
+        Some(Ok(head.row))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Runs, MAX_FAN_IN};
+    use crate::{Headers, Row, SortKey, sort::SortKind};
+    use std::fs;
+
+    #[test]
+    fn test_merge_in_several_passes() {
+        let headers = Headers::from_row(Row::from(vec!["n"]));
+        let keys = vec![SortKey::asc("n", SortKind::Num)];
+        let mut runs = Runs::new().unwrap();
+        let total = MAX_FAN_IN * MAX_FAN_IN + 3;
+
+        // run i holds i and i + total so every run overlaps the others
+        for i in 0..total {
+            let rows = [
+                Row::from(vec![i.to_string().as_str()]),
+                Row::from(vec![(i + total).to_string().as_str()]),
+            ];
+
+            runs.write(rows.iter()).unwrap();
+        }
+
+        let merged: Vec<usize> = runs.merge(Vec::new(), keys, headers)
+            .unwrap()
+            .map(|row| row.unwrap().get(0).unwrap().parse().unwrap())
+            .collect();
+
+        assert_eq!(merged, (0..2 * total).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_read_error_keeps_previous_row() {
+        let headers = Headers::from_row(Row::from(vec!["n"]));
+        let keys = vec![SortKey::asc("n", SortKind::Num)];
+        let mut runs = Runs::new().unwrap();
+
+        runs.write([Row::from(vec!["1"]), Row::from(vec!["2"])].iter()).unwrap();
+        // the second row is not valid UTF-8
+        fs::write(&runs.paths[0], b"1\n\xff\n").unwrap();
+
+        let mut merge = runs.merge(Vec::new(), keys, headers).unwrap();
+
+        assert_eq!(merge.next().unwrap().unwrap(), Row::from(vec!["1"]));
+        assert!(merge.next().unwrap().is_err());
+        assert!(merge.next().is_none());
+    }
+
+    #[test]
+    fn test_run_is_read_again_after_error() {
+        let headers = Headers::from_row(Row::from(vec!["n"]));
+        let keys = vec![SortKey::asc("n", SortKind::Num)];
+        let mut runs = Runs::new().unwrap();
+
+        runs.write([Row::from(vec!["1"]), Row::from(vec!["3"]), Row::from(vec!["5"])].iter()).unwrap();
+        runs.write([Row::from(vec!["2"]), Row::from(vec!["4"])].iter()).unwrap();
+        fs::write(&runs.paths[0], b"1\n\xff\n5\n").unwrap();
+
+        let merged: Vec<Option<String>> = runs.merge(vec![], keys, headers)
+            .unwrap()
+            .map(|row| row.ok().map(|row| row[0].to_string()))
+            .collect();
+
+        assert_eq!(
+            merged,
+            vec![Some("1".into()), None, Some("2".into()), Some("4".into()), Some("5".into())]
+        );
+    }
+
+    #[test]
+    fn test_error_for_every_missing_row() {
+        let headers = Headers::from_row(Row::from(vec!["n"]));
+        let keys = vec![SortKey::asc("n", SortKind::Num)];
+        let mut runs = Runs::new().unwrap();
+
+        runs.write([Row::from(vec!["1"]), Row::from(vec!["2"]), Row::from(vec!["3"])].iter()).unwrap();
+        fs::write(&runs.paths[0], b"1\n").unwrap();
+
+        let merged: Vec<bool> = runs.merge(vec![], keys, headers)
+            .unwrap()
+            .map(|row| row.is_ok())
+            .collect();
+
+        assert_eq!(merged, vec![true, false, false]);
+    }
+}
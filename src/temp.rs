@@ -0,0 +1,39 @@
+//! Temporary storage on disk for processors that can't keep everything in
+//! memory
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// A temporary directory that is removed with all its contents when dropped.
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Creates a new directory inside the system's temporary directory. The
+    /// prefix helps telling which processor created it.
+    pub fn new(prefix: &str) -> io::Result<TempDir> {
+        let path = env::temp_dir().join(format!(
+            "{}-{}-{}",
+            prefix,
+            process::id(),
+            rand::random::<u64>(),
+        ));
+
+        fs::create_dir_all(&path)?;
+
+        Ok(TempDir { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}